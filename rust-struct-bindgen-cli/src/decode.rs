@@ -0,0 +1,101 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use anyhow::{anyhow, bail, Context};
+use clap::Args;
+use rust_struct_bindgen_impl::dynamic::decode_with_options;
+
+use crate::{json::value_to_json, parse_type_ref, tree::format_tree, BtfInput, ValueOptions};
+
+#[derive(Args)]
+pub(crate) struct DecodeArgs {
+    #[arg(
+        help = "The type to decode with. Either a type name or a type id",
+        short = 't',
+        long = "type"
+    )]
+    ty: String,
+    #[command(flatten)]
+    input: BtfInput,
+    #[command(flatten)]
+    values: ValueOptions,
+    #[arg(help = "Print a C-like tree instead of JSON", long = "tree")]
+    tree: bool,
+    #[arg(
+        help = "Start reading from this byte offset of the data file",
+        long = "offset",
+        default_value_t = 0
+    )]
+    offset: usize,
+    #[arg(
+        help = "Decode this many consecutive records, and print them as an array",
+        short = 'n',
+        long = "count"
+    )]
+    count: Option<usize>,
+    #[arg(
+        help = "Distance in bytes between two consecutive records. Defaults to the size of the type",
+        long = "stride"
+    )]
+    stride: Option<usize>,
+    #[arg(help = "The ELF file path. If with `use_btf`, should be the btf archive path")]
+    file_path: String,
+    #[arg(help = "The binary dump to decode")]
+    data_path: String,
+}
+
+pub(crate) fn decode_command(args: DecodeArgs) -> anyhow::Result<()> {
+    let files = args.input.read(&args.file_path)?;
+    let btf = files.load()?;
+    let data =
+        std::fs::read(&args.data_path).with_context(|| anyhow!("Failed to read data file"))?;
+
+    let ty_id = parse_type_ref(&args.ty).resolve(&btf)?;
+    let size = btf.get_size_of(ty_id) as usize;
+    let stride = args.stride.unwrap_or(size);
+    if stride < size {
+        bail!(
+            "Stride {} is smaller than the size of the type ({} bytes)",
+            stride,
+            size
+        );
+    }
+    let options = args.values.bindgen_options();
+    let mut values = vec![];
+    for i in 0..args.count.unwrap_or(1) {
+        let (start, end) = i
+            .checked_mul(stride)
+            .and_then(|v| v.checked_add(args.offset))
+            .and_then(|start| Some((start, start.checked_add(size)?)))
+            .ok_or_else(|| anyhow!("Offset of record {} is out of range", i))?;
+        let record = data.get(start..end).ok_or_else(|| {
+            anyhow!(
+                "Record {} (bytes {}..{}) is out of the data file, which has {} bytes",
+                i,
+                start,
+                end,
+                data.len()
+            )
+        })?;
+        values.push(
+            decode_with_options(&btf, ty_id, record, &options)
+                .with_context(|| anyhow!("Failed to decode record {}", i))?,
+        );
+    }
+    if args.tree {
+        for value in values.iter() {
+            println!("{}", format_tree(value));
+        }
+    } else {
+        // An explicit `--count` always yields an array, so that the output shape doesn't depend on the count
+        let json = if args.count.is_some() {
+            serde_json::Value::Array(values.iter().map(value_to_json).collect())
+        } else {
+            value_to_json(&values[0])
+        };
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    }
+    Ok(())
+}
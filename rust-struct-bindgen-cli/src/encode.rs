@@ -0,0 +1,57 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::io::Write;
+
+use anyhow::{anyhow, Context};
+use clap::Args;
+use rust_struct_bindgen_impl::dynamic::encode_with_options;
+
+use crate::{json::json_to_value, parse_type_ref, BtfInput, ValueOptions};
+
+#[derive(Args)]
+pub(crate) struct EncodeArgs {
+    #[arg(
+        help = "The type to encode as. Either a type name or a type id",
+        short = 't',
+        long = "type"
+    )]
+    ty: String,
+    #[command(flatten)]
+    input: BtfInput,
+    #[command(flatten)]
+    values: ValueOptions,
+    #[arg(help = "The ELF file path. If with `use_btf`, should be the btf archive path")]
+    file_path: String,
+    #[arg(help = "The JSON document to encode")]
+    json_path: String,
+    #[arg(help = "Out file. If not given, write to stdout", short = 'o')]
+    out_file: Option<String>,
+}
+
+pub(crate) fn encode_command(args: EncodeArgs) -> anyhow::Result<()> {
+    let files = args.input.read(&args.file_path)?;
+    let btf = files.load()?;
+    let json_str =
+        std::fs::read_to_string(&args.json_path).with_context(|| anyhow!("Failed to read JSON"))?;
+    let json: serde_json::Value =
+        serde_json::from_str(&json_str).with_context(|| anyhow!("Failed to parse JSON"))?;
+
+    let ty_id = parse_type_ref(&args.ty).resolve(&btf)?;
+    let root = match btf.type_by_id(ty_id).name() {
+        "" => "value",
+        s => s,
+    };
+    let value = json_to_value(&json, root)?;
+    let bytes = encode_with_options(&btf, ty_id, &value, &args.values.bindgen_options())?;
+    if let Some(p) = args.out_file {
+        std::fs::write(p, bytes).with_context(|| anyhow!("Failed to write"))?;
+    } else {
+        std::io::stdout()
+            .write_all(&bytes)
+            .with_context(|| anyhow!("Failed to write"))?;
+    }
+    Ok(())
+}
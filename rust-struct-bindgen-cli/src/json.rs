@@ -0,0 +1,76 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use anyhow::bail;
+use rust_struct_bindgen_impl::dynamic::Value;
+use serde_json::{json, Map};
+
+/// Convert a decoded value to JSON. Enums become the name of their enumerators, integers which don't fit in 64 bits become strings, and union members which can't be decoded become `null`
+pub(crate) fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Struct(fields) | Value::Union(fields) => serde_json::Value::Object(
+            fields
+                .iter()
+                .map(|(name, v)| (name.clone(), value_to_json(v)))
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(values) => {
+            serde_json::Value::Array(values.iter().map(value_to_json).collect())
+        }
+        Value::Int(v) => {
+            if let Ok(v) = i64::try_from(*v) {
+                json!(v)
+            } else if let Ok(v) = u64::try_from(*v) {
+                json!(v)
+            } else {
+                json!(v.to_string())
+            }
+        }
+        Value::Bool(v) => json!(v),
+        // NaN and infinity are not representable in JSON, so they become `null`
+        Value::Float(v) => json!(v),
+        Value::Enum { name, .. } => json!(name),
+        Value::String(s) => json!(s),
+        Value::Pointer(v) => json!(v),
+        Value::Invalid(_) => serde_json::Value::Null,
+    }
+}
+
+/// Convert JSON to a value to be encoded. Numbers without a fractional part become integers, and objects become structs. `null` members of objects are skipped, so decoded unions can be encoded back
+pub(crate) fn json_to_value(json: &serde_json::Value, path: &str) -> anyhow::Result<Value> {
+    Ok(match json {
+        serde_json::Value::Null => bail!("{}: null is not supported", path),
+        serde_json::Value::Bool(v) => Value::Bool(*v),
+        serde_json::Value::Number(v) => {
+            if let Some(v) = v.as_i64() {
+                Value::Int(v as i128)
+            } else if let Some(v) = v.as_u64() {
+                Value::Int(v as i128)
+            } else {
+                Value::Float(v.as_f64().unwrap())
+            }
+        }
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| json_to_value(v, &format!("{}[{}]", path, i)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        serde_json::Value::Object(fields) => Value::Struct(
+            fields
+                .iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(name, v)| {
+                    Ok((
+                        name.clone(),
+                        json_to_value(v, &format!("{}.{}", path, name))?,
+                    ))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    })
+}
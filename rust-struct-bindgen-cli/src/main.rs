@@ -1,4 +1,3 @@
-
 //!  SPDX-License-Identifier: MIT
 //!
 //! Copyright (c) 2023, eunomia-bpf
@@ -6,52 +5,221 @@
 //!
 
 use anyhow::{anyhow, Context};
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use decode::{decode_command, DecodeArgs};
+use encode::{encode_command, EncodeArgs};
 use rust_struct_bindgen_impl::{
-    btf::types::Btf, generate_bindgen_token_stream, helper::create_elf_with_btf_section,
-    object::ElfFile,
+    btf::types::Btf, dynamic::TypeRef, helper::load_btf, BindgenOptions, Builder, StringPolicy,
 };
+
+mod decode;
+mod encode;
+mod json;
+mod tree;
+
 #[derive(Parser)]
-#[command(about, long_about, version)]
+#[command(
+    about,
+    long_about,
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg(
-        help = "The provided file is a plain btf archive",
-        short = 'b',
-        long = "btf"
-    )]
-    use_btf: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    input: BtfInput,
     #[arg(
         help = "Formatted the generated code. Requires the installation of `rustfmt`",
         short = 'f',
         long = "format"
     )]
     format: bool,
-    #[arg(help = "The ELF file path. If with `use_btf`, should be the btf archive path")]
-    file_path: String,
+    #[arg(
+        help = "The ELF file path. If with `use_btf`, should be the btf archive path",
+        required = true
+    )]
+    file_path: Option<String>,
     #[arg(help = "Out file. If not given, print to stdout", short = 'o')]
     out_file: Option<String>,
+    #[arg(
+        help = "Only generate types whose names match the regex, and the types they refer to. Can be given multiple times",
+        long = "allowlist-type",
+        value_name = "REGEX"
+    )]
+    allowlist_type: Vec<String>,
+    #[arg(
+        help = "Don't generate types whose names match the regex, unless other generated types refer to them. Can be given multiple times",
+        long = "blocklist-type",
+        value_name = "REGEX"
+    )]
+    blocklist_type: Vec<String>,
+    #[command(flatten)]
+    values: ValueOptions,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode a binary dump with a type in the BTF, and print it as JSON or a C-like tree
+    Decode(DecodeArgs),
+    /// Encode a JSON document as a type in the BTF, producing its native byte layout
+    Encode(EncodeArgs),
+}
+
+impl Args {
+    /// Map the flags onto the options of the generator
+    fn bindgen_options(&self) -> BindgenOptions {
+        BindgenOptions {
+            allowlist_type: self.allowlist_type.clone(),
+            blocklist_type: self.blocklist_type.clone(),
+            ..self.values.bindgen_options()
+        }
+    }
+}
+
+/// How to load the BTF file, shared by all commands
+#[derive(ClapArgs)]
+// Keep the doc comment out of the help of the commands flattening it
+#[command(about = None, long_about = None)]
+pub(crate) struct BtfInput {
+    #[arg(
+        help = "The provided file is a plain btf archive",
+        short = 'b',
+        long = "btf"
+    )]
+    use_btf: bool,
+    #[arg(
+        help = "The base BTF of the split BTF in the file, like `/sys/kernel/btf/vmlinux` for the BTF of a kernel module. Must be a plain btf archive",
+        long = "base-btf",
+        value_name = "PATH"
+    )]
+    base_btf: Option<String>,
+}
+
+/// How enums and char arrays are represented, shared by all commands so that `decode` and `encode` agree with the generated code
+#[derive(ClapArgs)]
+#[command(about = None, long_about = None)]
+pub(crate) struct ValueOptions {
+    #[arg(
+        help = "Generate enums whose names match the regex as open enums, keeping unknown values in an `Unknown` variant. Can be given multiple times",
+        long = "open-enum",
+        value_name = "REGEX"
+    )]
+    open_enum: Vec<String>,
+    #[arg(
+        help = "Generate enums whose names match the regex as bit flags. Can be given multiple times",
+        long = "bitflags-enum",
+        value_name = "REGEX"
+    )]
+    bitflags_enum: Vec<String>,
+    #[arg(
+        help = "Also generate enums with at least three members whose values are distinct powers of two as bit flags, unless given to --open-enum",
+        long = "detect-bitflags"
+    )]
+    detect_bitflags: bool,
+    #[arg(
+        help = "How arrays of `char` and `signed char` are represented: strict, lossy, nul_optional, cstring, bytes or raw. Arrays of `unsigned char` (like `__u8`) are `[u8; N]` (raw) by default",
+        long = "string-policy",
+        value_name = "POLICY",
+        default_value = "strict"
+    )]
+    string_policy: StringPolicy,
+    #[arg(
+        help = "Represent the arrays whose element type has a name matching the regex by the policy, like `__u8=nul_optional`. Typedefs of the element type are also matched. Can be given multiple times, and the first match wins",
+        long = "type-string-policy",
+        value_name = "REGEX=POLICY"
+    )]
+    type_string_policy: Vec<String>,
+    #[arg(
+        help = "Represent the char array fields whose `<struct or union name>.<field name>` match the regex by the policy. Can be given multiple times, and the first match wins",
+        long = "field-string-policy",
+        value_name = "REGEX=POLICY"
+    )]
+    field_string_policy: Vec<String>,
+}
+
+impl ValueOptions {
+    /// Map the flags onto the options of the generator, leaving the type filters empty
+    pub(crate) fn bindgen_options(&self) -> BindgenOptions {
+        BindgenOptions {
+            open_enum: self.open_enum.clone(),
+            bitflags_enum: self.bitflags_enum.clone(),
+            detect_bitflags: self.detect_bitflags,
+            string_policy: self.string_policy,
+            type_string_policy: self.type_string_policy.clone(),
+            field_string_policy: self.field_string_policy.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The content of the BTF file, and of its base BTF if given
+pub(crate) struct BtfFiles {
+    use_btf: bool,
+    base: Option<Vec<u8>>,
+    file: Vec<u8>,
+}
+
+impl BtfInput {
+    /// Read the BTF file, and the base BTF if given
+    pub(crate) fn read(&self, file_path: &str) -> anyhow::Result<BtfFiles> {
+        Ok(BtfFiles {
+            use_btf: self.use_btf,
+            base: self.base_btf.as_deref().map(read_input_file).transpose()?,
+            file: read_input_file(file_path)?,
+        })
+    }
+}
+
+impl BtfFiles {
+    /// Parse the BTF, as a split BTF on top of the base BTF if given
+    pub(crate) fn load(&self) -> anyhow::Result<Btf<'_>> {
+        let base = self
+            .base
+            .as_deref()
+            .map(Btf::from_bytes)
+            .transpose()
+            .context("Failed to parse the base BTF")?;
+        load_btf(&self.file, self.use_btf, base.as_ref()).context("Failed to parse BTF")
+    }
 }
+
+/// Read the input file holding the BTF
+pub(crate) fn read_input_file(file_path: &str) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(file_path).with_context(|| anyhow!("Failed to read input file"))
+}
+
+/// A type given on the command line, either a type id or a type name
+pub(crate) fn parse_type_ref(ty: &str) -> TypeRef<'_> {
+    match ty.parse::<u32>() {
+        Ok(id) => TypeRef::Id(id),
+        Err(_) => TypeRef::Name(ty),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let file_buf =
-        std::fs::read(&args.file_path).with_context(|| anyhow!("Failed to read input file"))?;
-    let elf_bin = if args.use_btf {
-        create_elf_with_btf_section(&file_buf, true)
-            .with_context(|| anyhow!("Failed to convert BTF into ELF"))?
-    } else {
-        file_buf
-    };
-    let elf = ElfFile::parse(&elf_bin).map_err(|e| anyhow!("Failed to parse ELF: {}", e))?;
-    let btf = Btf::load(&elf).map_err(|e| anyhow!("Failed to parse BTF: {}", e))?;
-    let generated_source = generate_bindgen_token_stream(&btf)
-        .with_context(|| anyhow!("Failed to generate rust code"))?
-        .to_string();
-    let generated_source = if args.format {
-        rustfmt_wrapper::rustfmt(generated_source)
-            .with_context(|| anyhow!("Failed to format the code"))?
+    match args.command {
+        Some(Command::Decode(decode_args)) => decode_command(decode_args),
+        Some(Command::Encode(encode_args)) => encode_command(encode_args),
+        None => generate_command(args),
+    }
+}
+
+fn generate_command(args: Args) -> anyhow::Result<()> {
+    let file_path = args.file_path.as_deref().unwrap();
+    let mut builder = if args.input.use_btf {
+        Builder::from_btf_path(file_path)
     } else {
-        generated_source
-    };
+        Builder::from_elf_path(file_path)
+    }
+    .rustfmt(args.format);
+    if let Some(base_btf) = &args.input.base_btf {
+        builder = builder.base_btf_path(base_btf);
+    }
+    *builder.options_mut() = args.bindgen_options();
+    let generated_source = builder.generate_source()?;
     if let Some(p) = args.out_file {
         std::fs::write(p, generated_source).with_context(|| anyhow!("Failed to write"))?;
     } else {
@@ -0,0 +1,66 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::fmt::Write;
+
+use rust_struct_bindgen_impl::dynamic::Value;
+
+const INDENT: &str = "    ";
+
+/// Format a decoded value like a C designated initializer, e.g. `{ .a = 1, .s = "x" }`, one member per line
+pub(crate) fn format_tree(value: &Value) -> String {
+    let mut out = String::new();
+    write_tree(&mut out, value, 0);
+    out
+}
+
+fn write_tree(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Struct(fields) | Value::Union(fields) => {
+            if fields.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            out.push_str("{\n");
+            for (name, v) in fields.iter() {
+                write!(out, "{}.{} = ", INDENT.repeat(depth + 1), name).unwrap();
+                write_tree(out, v, depth + 1);
+                out.push_str(",\n");
+            }
+            write!(out, "{}}}", INDENT.repeat(depth)).unwrap();
+        }
+        Value::Array(values) => {
+            // Arrays of scalars are kept in a single line
+            if values
+                .iter()
+                .all(|v| !matches!(v, Value::Struct(_) | Value::Union(_) | Value::Array(_)))
+            {
+                out.push_str("{ ");
+                for (i, v) in values.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    write_tree(out, v, depth);
+                }
+                out.push_str(" }");
+                return;
+            }
+            out.push_str("{\n");
+            for v in values.iter() {
+                out.push_str(&INDENT.repeat(depth + 1));
+                write_tree(out, v, depth + 1);
+                out.push_str(",\n");
+            }
+            write!(out, "{}}}", INDENT.repeat(depth)).unwrap();
+        }
+        Value::Int(v) => write!(out, "{}", v).unwrap(),
+        Value::Bool(v) => write!(out, "{}", v).unwrap(),
+        Value::Float(v) => write!(out, "{:?}", v).unwrap(),
+        Value::Enum { name, value } => write!(out, "{} /* {} */", name, value).unwrap(),
+        Value::String(s) => write!(out, "{:?}", s).unwrap(),
+        Value::Pointer(v) => write!(out, "{:#x}", v).unwrap(),
+        Value::Invalid(e) => write!(out, "/* invalid: {} */", e).unwrap(),
+    }
+}
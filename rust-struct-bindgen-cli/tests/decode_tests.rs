@@ -0,0 +1,216 @@
+mod util;
+
+#[test]
+fn test_decode_json() {
+    let output = util::run_cli(&[
+        "decode",
+        "--type",
+        "S",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(json["str"], "A-String");
+    assert_eq!(json["u32v"], 0x12345678);
+    assert_eq!(json["i64v"], -0x123456789abcdef0i64);
+    assert_eq!(json["dbl"], 4.56);
+    // Enums are the names of their enumerators
+    assert_eq!(json["e"], "E_C");
+    assert_eq!(json["arr1"][1][2][3], 0x10203);
+    for i in 0..10 {
+        assert_eq!(json["str_arr"][i], format!("hello {}", i));
+    }
+    // Fields keep the order of the struct
+    let names = json.as_object().unwrap().keys().collect::<Vec<_>>();
+    assert_eq!(names[..3], ["arr1", "str", "str_arr"]);
+
+    // An explicit count yields an array of records
+    let output = util::run_cli(&[
+        "decode",
+        "-t",
+        "S",
+        "-n",
+        "1",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    let records: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(records, serde_json::Value::Array(vec![json]));
+}
+
+/// `arr1` of `S` in dumper_test.bin starts with the ints 0, 1, 2, 3, 256, 257, 258, ...
+fn decode_ints(extra: &[&str]) -> serde_json::Value {
+    let mut args = vec!["decode", "-t", "int"];
+    args.extend_from_slice(extra);
+    args.extend_from_slice(&["simple_prog.bpf.o", "dumper_test.bin"]);
+    serde_json::from_slice(&util::run_cli(&args)).unwrap()
+}
+
+#[test]
+fn test_decode_count() {
+    assert_eq!(decode_ints(&[]), serde_json::json!(0));
+    assert_eq!(
+        decode_ints(&["-n", "6"]),
+        serde_json::json!([0, 1, 2, 3, 256, 257])
+    );
+}
+
+#[test]
+fn test_decode_offset() {
+    assert_eq!(decode_ints(&["--offset", "16"]), serde_json::json!(256));
+    assert_eq!(
+        decode_ints(&["--offset", "8", "-n", "3"]),
+        serde_json::json!([2, 3, 256])
+    );
+}
+
+#[test]
+fn test_decode_stride() {
+    assert_eq!(
+        decode_ints(&["--stride", "16", "-n", "3"]),
+        serde_json::json!([0, 256, 512])
+    );
+    assert_eq!(
+        decode_ints(&["--offset", "8", "--stride", "16", "-n", "3"]),
+        serde_json::json!([2, 258, 514])
+    );
+    let err = util::run_cli_err(&[
+        "decode",
+        "-t",
+        "int",
+        "--stride",
+        "2",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    assert!(err.contains("Stride 2 is smaller than the size of the type (4 bytes)"));
+}
+
+#[test]
+fn test_decode_tree() {
+    let output = util::run_cli(&[
+        "decode",
+        "-t",
+        "S",
+        "--tree",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("{\n    .arr1 = {\n"));
+    assert!(output.contains(".str = \"A-String\",\n"));
+    assert!(output.contains("{ 0, 1, 2, 3 },\n"));
+    assert!(output.contains(".e = E_C /* 2 */,\n"));
+}
+
+#[test]
+fn test_decode_past_end() {
+    // dumper_test.bin has 368 bytes
+    let err = util::run_cli_err(&[
+        "decode",
+        "-t",
+        "int",
+        "--offset",
+        "366",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    assert!(err.contains("Record 0 (bytes 366..370) is out of the data file, which has 368 bytes"));
+    let err = util::run_cli_err(&[
+        "decode",
+        "-t",
+        "S",
+        "-n",
+        "2",
+        "simple_prog.bpf.o",
+        "dumper_test.bin",
+    ]);
+    assert!(err.contains("Record 1 (bytes 368..736) is out of the data file"));
+}
+
+#[test]
+fn test_decode_offset_overflow() {
+    let max = usize::MAX.to_string();
+    for extra in [["--offset", max.as_str()], ["--stride", max.as_str()]] {
+        let mut args = vec!["decode", "-t", "int", "-n", "2"];
+        args.extend_from_slice(&extra);
+        args.extend_from_slice(&["simple_prog.bpf.o", "dumper_test.bin"]);
+        let err = util::run_cli_err(&args);
+        assert!(err.contains("is out of range"), "{}", err);
+    }
+}
+
+#[test]
+fn test_decode_options() {
+    let tmp_dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    // A `comm[16]` filled up without a NUL byte
+    let str_path = tmp_dir.join("full_comm.bin");
+    std::fs::write(&str_path, b"abcdefghijklmnopname\0\0\0\0").unwrap();
+    let str_path = str_path.to_str().unwrap();
+    let err = util::run_cli_err(&["decode", "-t", "STR", "string_prog.bpf.o", str_path]);
+    assert!(err.contains("Failed to decode field `comm`"), "{}", err);
+    let decode_str = |extra: &[&str]| -> serde_json::Value {
+        serde_json::from_slice(&util::run_cli(
+            &[
+                &["decode", "-t", "STR"],
+                extra,
+                &["string_prog.bpf.o", str_path],
+            ]
+            .concat(),
+        ))
+        .unwrap()
+    };
+    let json = decode_str(&["--string-policy", "nul_optional"]);
+    assert_eq!(json["comm"], "abcdefghijklmnop");
+    assert_eq!(json["name"], "name");
+    let json = decode_str(&["--field-string-policy", "STR.comm=raw"]);
+    assert_eq!(json["comm"], serde_json::json!(b"abcdefghijklmnop"));
+    assert_eq!(json["name"], "name");
+    let json = decode_str(&["--type-string-policy", "char=lossy"]);
+    assert_eq!(json["comm"], "abcdefghijklmnop");
+
+    // `perm` holds flags, and `opt` holds 7, which is not an enumerator of `Opt`
+    let fl_path = tmp_dir.join("unknown_enum.bin");
+    std::fs::write(&fl_path, [9, 0, 0, 0, 7, 0, 0, 0, 1, 0, 0, 0]).unwrap();
+    let fl_path = fl_path.to_str().unwrap();
+    let err = util::run_cli_err(&["decode", "-t", "FL", "flags_prog.bpf.o", fl_path]);
+    assert!(
+        err.contains("Invalid enum value 9 for enum Perm"),
+        "{}",
+        err
+    );
+    let err = util::run_cli_err(&[
+        "decode",
+        "-t",
+        "FL",
+        "--detect-bitflags",
+        "flags_prog.bpf.o",
+        fl_path,
+    ]);
+    assert!(err.contains("Invalid enum value 7 for enum Opt"), "{}", err);
+    let json: serde_json::Value = serde_json::from_slice(&util::run_cli(&[
+        "decode",
+        "-t",
+        "FL",
+        "--detect-bitflags",
+        "--open-enum",
+        "Opt",
+        "flags_prog.bpf.o",
+        fl_path,
+    ]))
+    .unwrap();
+    assert_eq!(json["opt"], 7);
+    assert_eq!(json["perm"], "P_READ | 0x8");
+    let json: serde_json::Value = serde_json::from_slice(&util::run_cli(&[
+        "decode",
+        "-t",
+        "FL",
+        "--detect-bitflags",
+        "--bitflags-enum",
+        "Opt",
+        "flags_prog.bpf.o",
+        fl_path,
+    ]))
+    .unwrap();
+    assert_eq!(json["opt"], "O_A | O_B | 0x4");
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+mod util;
+
+#[test]
+fn test_decode_encode_round_trip() {
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    for (elf_name, bin_name, ty, extra_args) in [
+        ("simple_prog.bpf.o", "dumper_test.bin", "S", &[][..]),
+        ("bitfield_prog.bpf.o", "bitfield_dump.bin", "BH", &[]),
+        ("union_prog.bpf.o", "union_dump.bin", "T", &[]),
+        // Split BTF on top of its base
+        (
+            "union_prog_split.btf",
+            "union_dump.bin",
+            "T",
+            &["--btf", "--base-btf", "union_prog.btf"],
+        ),
+    ] {
+        let json =
+            util::run_cli(&[&["decode", "--type", ty], extra_args, &[elf_name, bin_name]].concat());
+        let json_path = tmp_dir.join(format!("{}.json", bin_name));
+        std::fs::write(&json_path, json).unwrap();
+
+        // Written to stdout
+        let bytes = util::run_cli(
+            &[
+                &["encode", "--type", ty],
+                extra_args,
+                &[elf_name, json_path.to_str().unwrap()],
+            ]
+            .concat(),
+        );
+        let bin_data = std::fs::read(util::get_assets_dir().join(bin_name)).unwrap();
+        assert_eq!(bytes, bin_data, "{}", bin_name);
+
+        // And to a file
+        let out_path = tmp_dir.join(bin_name);
+        util::run_cli(
+            &[
+                &["encode", "--type", ty],
+                extra_args,
+                &[
+                    elf_name,
+                    json_path.to_str().unwrap(),
+                    "-o",
+                    out_path.to_str().unwrap(),
+                ],
+            ]
+            .concat(),
+        );
+        assert_eq!(std::fs::read(&out_path).unwrap(), bin_data, "{}", bin_name);
+    }
+}
+
+#[test]
+fn test_encode_options() {
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let json_path = tmp_dir.join("options.json");
+    std::fs::write(
+        &json_path,
+        r#"{"comm": "abcdefghijklmnop", "name": "name", "tail": ""}"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_str().unwrap();
+    // Strict strings leave room for the NUL byte
+    let err = util::run_cli_err(&["encode", "-t", "STR", "string_prog.bpf.o", json_path]);
+    assert!(err.contains("comm"), "{}", err);
+    let bytes = util::run_cli(&[
+        "encode",
+        "-t",
+        "STR",
+        "--string-policy",
+        "nul_optional",
+        "string_prog.bpf.o",
+        json_path,
+    ]);
+    assert_eq!(bytes, b"abcdefghijklmnopname\0\0\0\0");
+
+    std::fs::write(
+        json_path,
+        r#"{"perm": "P_READ", "opt": 7, "small": "P_EXEC"}"#,
+    )
+    .unwrap();
+    let err = util::run_cli_err(&["encode", "-t", "FL", "flags_prog.bpf.o", json_path]);
+    assert!(err.contains("opt"), "{}", err);
+    let bytes = util::run_cli(&[
+        "encode",
+        "-t",
+        "FL",
+        "--open-enum",
+        "Opt",
+        "flags_prog.bpf.o",
+        json_path,
+    ]);
+    assert_eq!(bytes, [1, 0, 0, 0, 7, 0, 0, 0, 4, 0, 0, 0]);
+}
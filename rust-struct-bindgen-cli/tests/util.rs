@@ -0,0 +1,36 @@
+use std::{path::PathBuf, process::Command};
+
+/// The assets are shared with the proc macro crate
+#[allow(unused)]
+pub(crate) fn get_assets_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../rust-struct-bindgen-proc-macro/assets")
+}
+
+/// Run the CLI in the assets directory, and return its stdout. Panics if it fails
+#[allow(unused)]
+pub(crate) fn run_cli(args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-struct-bindgen-cli"))
+        .current_dir(get_assets_dir())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+/// Run the CLI in the assets directory, and return its stderr. Panics if it succeeds
+#[allow(unused)]
+pub(crate) fn run_cli_err(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-struct-bindgen-cli"))
+        .current_dir(get_assets_dir())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{:?} should fail", args);
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,509 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+//! Loader of BTF, either from a plain btf archive (like `/sys/kernel/btf/vmlinux`), or from the `.BTF` section of an ELF file.
+//! Both byte orders are accepted, which is detected by the magic.
+//!
+//! Split BTF, like the BTF of kernel modules, is loaded on top of its base BTF. Its type ids and string offsets continue from the base.
+
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Context, Result};
+use object::{ElfFile, Object, ObjectSection};
+
+use self::types::*;
+
+/// Definitions of BTF types
+pub mod types;
+
+const BTF_MAGIC: u16 = 0xeb9f;
+const BTF_VERSION: u8 = 1;
+const BTF_HEADER_SIZE: usize = 24;
+const BTF_TYPE_SIZE: usize = 12;
+
+const BTF_KIND_INT: u32 = 1;
+const BTF_KIND_PTR: u32 = 2;
+const BTF_KIND_ARRAY: u32 = 3;
+const BTF_KIND_STRUCT: u32 = 4;
+const BTF_KIND_UNION: u32 = 5;
+const BTF_KIND_ENUM: u32 = 6;
+const BTF_KIND_FWD: u32 = 7;
+const BTF_KIND_TYPEDEF: u32 = 8;
+const BTF_KIND_VOLATILE: u32 = 9;
+const BTF_KIND_CONST: u32 = 10;
+const BTF_KIND_RESTRICT: u32 = 11;
+const BTF_KIND_FUNC: u32 = 12;
+const BTF_KIND_FUNC_PROTO: u32 = 13;
+const BTF_KIND_VAR: u32 = 14;
+const BTF_KIND_DATASEC: u32 = 15;
+const BTF_KIND_FLOAT: u32 = 16;
+const BTF_KIND_DECL_TAG: u32 = 17;
+const BTF_KIND_TYPE_TAG: u32 = 18;
+const BTF_KIND_ENUM64: u32 = 19;
+
+const BTF_INT_SIGNED: u32 = 1;
+const BTF_INT_CHAR: u32 = 2;
+const BTF_INT_BOOL: u32 = 4;
+
+/// Reads integers in the byte order of the BTF, reporting truncated data as errors
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes<const N: usize>(&self, off: usize) -> Result<[u8; N]> {
+        self.data
+            .get(off..off + N)
+            .map(|v| v.try_into().unwrap())
+            .ok_or_else(|| anyhow!("Unexpected end of BTF data at offset {}", off))
+    }
+    fn u16(&self, off: usize) -> Result<u16> {
+        let b = self.bytes(off)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+    fn u32(&self, off: usize) -> Result<u32> {
+        let b = self.bytes(off)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+    /// Get a subslice of `len` bytes at `off`
+    fn slice(&self, off: usize, len: usize, what: &str) -> Result<Reader<'a>> {
+        let data = off
+            .checked_add(len)
+            .and_then(|end| self.data.get(off..end))
+            .ok_or_else(|| anyhow!("{} is out of the BTF data", what))?;
+        Ok(Reader { data, ..*self })
+    }
+}
+
+/// The string sections, from which names are referred by offsets. Offsets of a split BTF continue from its base
+struct Strings<'a>(Vec<&'a [u8]>);
+
+impl<'a> Strings<'a> {
+    fn get(&self, off: u32) -> Result<&'a str> {
+        let mut local_off = off as usize;
+        let mut rest = None;
+        for section in self.0.iter() {
+            if local_off < section.len() {
+                rest = Some(&section[local_off..]);
+                break;
+            }
+            local_off -= section.len();
+        }
+        let rest = rest.ok_or_else(|| anyhow!("Invalid string offset {}", off))?;
+        let len = rest
+            .iter()
+            .position(|v| *v == 0)
+            .ok_or_else(|| anyhow!("String at offset {} is not terminated", off))?;
+        std::str::from_utf8(&rest[..len])
+            .with_context(|| anyhow!("String at offset {} is not valid UTF-8", off))
+    }
+}
+
+impl<'a> Btf<'a> {
+    /// Load the BTF from the `.BTF` section of the ELF file
+    pub fn load(elf: &ElfFile<'a>) -> Result<Btf<'a>> {
+        Self::parse(elf_btf_section(elf)?, Some(elf_ptr_sz(elf)), None)
+    }
+    /// Load the split BTF from the `.BTF` section of the ELF file, like a kernel module, on top of the base BTF
+    pub fn load_split(elf: &ElfFile<'a>, base: &Btf<'a>) -> Result<Btf<'a>> {
+        Self::parse(elf_btf_section(elf)?, Some(elf_ptr_sz(elf)), Some(base))
+    }
+    /// Load the BTF from an ELF file in memory
+    pub fn from_elf_bytes(data: &'a [u8]) -> Result<Btf<'a>> {
+        let elf = ElfFile::parse(data).map_err(|e| anyhow!("Failed to parse ELF: {}", e))?;
+        Self::load(&elf)
+    }
+    /// Load a plain btf archive, like `/sys/kernel/btf/vmlinux`.
+    ///
+    /// The pointer size isn't recorded in it, so it's guessed from the size of `long`, and defaults to 8
+    pub fn from_bytes(data: &'a [u8]) -> Result<Btf<'a>> {
+        Self::parse(data, None, None)
+    }
+    /// Load a plain split btf archive, like `/sys/kernel/btf/<module>`, on top of the base BTF
+    pub fn from_split_bytes(data: &'a [u8], base: &Btf<'a>) -> Result<Btf<'a>> {
+        Self::parse(data, None, Some(base))
+    }
+    fn parse(data: &'a [u8], ptr_sz: Option<u32>, base: Option<&Btf<'a>>) -> Result<Btf<'a>> {
+        if data.len() < BTF_HEADER_SIZE {
+            bail!("BTF data is too short: {} bytes", data.len());
+        }
+        let mut reader = Reader {
+            data,
+            big_endian: false,
+        };
+        match reader.u16(0)? {
+            BTF_MAGIC => {}
+            v if v.swap_bytes() == BTF_MAGIC => reader.big_endian = true,
+            v => bail!("Invalid BTF magic: {:#x}", v),
+        }
+        let version = data[2];
+        if version != BTF_VERSION {
+            bail!(
+                "Unsupported BTF version: {}, expected {}",
+                version,
+                BTF_VERSION
+            );
+        }
+        let hdr_len = reader.u32(4)? as usize;
+        if hdr_len < BTF_HEADER_SIZE {
+            bail!("Invalid BTF header length: {}", hdr_len);
+        }
+        let section = |off: usize, what: &str| -> Result<Reader<'a>> {
+            let start = hdr_len + reader.u32(off)? as usize;
+            reader.slice(start, reader.u32(off + 4)? as usize, what)
+        };
+        let type_data = section(8, "Type section")?;
+        let mut strings = base.map(|v| v.strings.clone()).unwrap_or_default();
+        strings.push(section(16, "String section")?.data);
+        let strings = Strings(strings);
+
+        let mut types = match base {
+            Some(base) => base.types.clone(),
+            None => vec![BtfType::Void],
+        };
+        let start_id = types.len() as u32;
+        let mut off = 0;
+        while off < type_data.data.len() {
+            let (ty, size) = load_type(type_data, off, &strings)
+                .with_context(|| anyhow!("Failed to load BTF type {}", types.len()))?;
+            types.push(ty);
+            off += size;
+        }
+        let ptr_sz = ptr_sz
+            .or(base.map(|v| v.ptr_sz))
+            .unwrap_or_else(|| guess_ptr_sz(&types));
+        let btf = Btf {
+            types,
+            ptr_sz,
+            start_id,
+            strings: strings.0,
+        };
+        check_types(&btf)?;
+        Ok(btf)
+    }
+}
+
+/// Check that the types of this BTF only refer to existing types, and that their sizes are computable, so that `type_by_id` and `get_size_of` never panic on them
+fn check_types(btf: &Btf) -> Result<()> {
+    for id in btf.start_id..btf.type_cnt() {
+        let ty = btf.type_by_id(id);
+        if let Some(referred) = referred_ids(ty).find(|v| *v >= btf.type_cnt()) {
+            bail!("BTF type {} refers to invalid type id {}", id, referred);
+        }
+        if btf.checked_size_of(id, 0).is_none() {
+            bail!(
+                "Size of BTF type {} overflows, or it's nested deeper than {} levels",
+                id,
+                MAX_RESOLVE_DEPTH
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Ids of the types referred by the type
+fn referred_ids<'b>(ty: &'b BtfType) -> Box<dyn Iterator<Item = u32> + 'b> {
+    match ty {
+        BtfType::Ptr(BtfPtr { type_id })
+        | BtfType::Typedef(BtfTypedef { type_id, .. })
+        | BtfType::Volatile(BtfVolatile { type_id })
+        | BtfType::Const(BtfConst { type_id })
+        | BtfType::Restrict(BtfRestrict { type_id })
+        | BtfType::Var(BtfVar { type_id, .. })
+        | BtfType::DeclTag(BtfDeclTag { type_id, .. })
+        | BtfType::TypeTag(BtfTypeTag { type_id, .. })
+        | BtfType::Func(BtfFunc {
+            proto_type_id: type_id,
+            ..
+        }) => Box::new(std::iter::once(*type_id)),
+        BtfType::Array(t) => Box::new([t.val_type_id, t.idx_type_id].into_iter()),
+        BtfType::Struct(t) | BtfType::Union(t) => Box::new(t.members.iter().map(|m| m.type_id)),
+        BtfType::FuncProto(t) => {
+            Box::new(std::iter::once(t.res_type_id).chain(t.params.iter().map(|p| p.type_id)))
+        }
+        BtfType::Datasec(t) => Box::new(t.vars.iter().map(|v| v.type_id)),
+        BtfType::Void
+        | BtfType::Int(_)
+        | BtfType::Enum(_)
+        | BtfType::Fwd(_)
+        | BtfType::Float(_) => Box::new(std::iter::empty()),
+    }
+}
+
+fn elf_btf_section<'a>(elf: &ElfFile<'a>) -> Result<&'a [u8]> {
+    let section = elf
+        .section_by_name(".BTF")
+        .ok_or_else(|| anyhow!("No .BTF section found"))?;
+    match section.data() {
+        Cow::Borrowed(data) => Ok(data),
+        Cow::Owned(_) => bail!("Compressed .BTF section is not supported"),
+    }
+}
+
+fn elf_ptr_sz(elf: &ElfFile) -> u32 {
+    if elf.elf().is_64 {
+        8
+    } else {
+        4
+    }
+}
+
+/// Load the type at `off` of the type section, returning it with its size in bytes
+fn load_type<'a>(
+    reader: Reader<'a>,
+    off: usize,
+    strs: &Strings<'a>,
+) -> Result<(BtfType<'a>, usize)> {
+    let name = strs.get(reader.u32(off)?)?;
+    let info = reader.u32(off + 4)?;
+    // It's a union of the size and the referred type id in C
+    let size_or_type = reader.u32(off + 8)?;
+    let kind = (info >> 24) & 0x1f;
+    let vlen = (info & 0xffff) as usize;
+    let kind_flag = info >> 31 == 1;
+    let extra = off + BTF_TYPE_SIZE;
+    let u32_at = |idx: usize| reader.u32(extra + idx * 4);
+
+    let (ty, extra_words) = match kind {
+        BTF_KIND_INT => {
+            let v = u32_at(0)?;
+            let encoding = (v >> 24) & 0xf;
+            let encoding = if encoding & BTF_INT_BOOL != 0 {
+                BtfIntEncoding::Bool
+            } else if encoding & BTF_INT_SIGNED != 0 {
+                // `signed char` has both the signed and the char bit
+                BtfIntEncoding::Signed
+            } else if encoding & BTF_INT_CHAR != 0 {
+                BtfIntEncoding::Char
+            } else {
+                BtfIntEncoding::None
+            };
+            let ty = BtfType::Int(BtfInt {
+                name,
+                bits: v & 0xff,
+                offset: (v >> 16) & 0xff,
+                encoding,
+            });
+            (ty, 1)
+        }
+        BTF_KIND_PTR => (
+            BtfType::Ptr(BtfPtr {
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_ARRAY => {
+            let ty = BtfType::Array(BtfArray {
+                val_type_id: u32_at(0)?,
+                idx_type_id: u32_at(1)?,
+                nelems: u32_at(2)?,
+            });
+            (ty, 3)
+        }
+        BTF_KIND_STRUCT | BTF_KIND_UNION => {
+            let members = (0..vlen)
+                .map(|i| {
+                    let offset = u32_at(i * 3 + 2)?;
+                    Ok(BtfMember {
+                        name: strs.get(u32_at(i * 3)?)?,
+                        type_id: u32_at(i * 3 + 1)?,
+                        // With the kind flag, the offset holds the bitfield size in its high 8 bits
+                        bit_offset: if kind_flag { offset & 0xffffff } else { offset },
+                        bit_size: if kind_flag { (offset >> 24) as u8 } else { 0 },
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let comp = BtfComposite {
+                is_struct: kind == BTF_KIND_STRUCT,
+                name,
+                sz: size_or_type,
+                members,
+            };
+            let ty = if kind == BTF_KIND_STRUCT {
+                BtfType::Struct(comp)
+            } else {
+                BtfType::Union(comp)
+            };
+            (ty, vlen * 3)
+        }
+        BTF_KIND_ENUM => {
+            let values = (0..vlen)
+                .map(|i| {
+                    let value = u32_at(i * 2 + 1)?;
+                    Ok(BtfEnumValue {
+                        name: strs.get(u32_at(i * 2)?)?,
+                        value: if kind_flag {
+                            value as i32 as i128
+                        } else {
+                            value as i128
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let ty = BtfType::Enum(BtfEnum {
+                name,
+                sz: size_or_type,
+                is_signed: kind_flag,
+                values,
+            });
+            (ty, vlen * 2)
+        }
+        BTF_KIND_ENUM64 => {
+            let values = (0..vlen)
+                .map(|i| {
+                    let lo = u32_at(i * 3 + 1)? as u64;
+                    let hi = u32_at(i * 3 + 2)? as u64;
+                    let value = (hi << 32) | lo;
+                    Ok(BtfEnumValue {
+                        name: strs.get(u32_at(i * 3)?)?,
+                        value: if kind_flag {
+                            value as i64 as i128
+                        } else {
+                            value as i128
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let ty = BtfType::Enum(BtfEnum {
+                name,
+                sz: size_or_type,
+                is_signed: kind_flag,
+                values,
+            });
+            (ty, vlen * 3)
+        }
+        BTF_KIND_FWD => {
+            let kind = if kind_flag {
+                BtfFwdKind::Union
+            } else {
+                BtfFwdKind::Struct
+            };
+            (BtfType::Fwd(BtfFwd { name, kind }), 0)
+        }
+        BTF_KIND_TYPEDEF => (
+            BtfType::Typedef(BtfTypedef {
+                name,
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_VOLATILE => (
+            BtfType::Volatile(BtfVolatile {
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_CONST => (
+            BtfType::Const(BtfConst {
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_RESTRICT => (
+            BtfType::Restrict(BtfRestrict {
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_FUNC => (
+            BtfType::Func(BtfFunc {
+                name,
+                proto_type_id: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_FUNC_PROTO => {
+            let params = (0..vlen)
+                .map(|i| {
+                    Ok(BtfFuncParam {
+                        name: strs.get(u32_at(i * 2)?)?,
+                        type_id: u32_at(i * 2 + 1)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let ty = BtfType::FuncProto(BtfFuncProto {
+                res_type_id: size_or_type,
+                params,
+            });
+            (ty, vlen * 2)
+        }
+        BTF_KIND_VAR => (
+            BtfType::Var(BtfVar {
+                name,
+                type_id: size_or_type,
+            }),
+            1,
+        ),
+        BTF_KIND_DATASEC => {
+            let vars = (0..vlen)
+                .map(|i| {
+                    Ok(BtfDatasecVar {
+                        type_id: u32_at(i * 3)?,
+                        offset: u32_at(i * 3 + 1)?,
+                        sz: u32_at(i * 3 + 2)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let ty = BtfType::Datasec(BtfDatasec {
+                name,
+                sz: size_or_type,
+                vars,
+            });
+            (ty, vlen * 3)
+        }
+        BTF_KIND_FLOAT => (
+            BtfType::Float(BtfFloat {
+                name,
+                sz: size_or_type,
+            }),
+            0,
+        ),
+        BTF_KIND_DECL_TAG => (
+            BtfType::DeclTag(BtfDeclTag {
+                name,
+                type_id: size_or_type,
+                comp_idx: u32_at(0)? as i32,
+            }),
+            1,
+        ),
+        BTF_KIND_TYPE_TAG => (
+            BtfType::TypeTag(BtfTypeTag {
+                name,
+                type_id: size_or_type,
+            }),
+            0,
+        ),
+        s => bail!("Unknown BTF kind: {}", s),
+    };
+    Ok((ty, BTF_TYPE_SIZE + extra_words * 4))
+}
+
+/// Guess the pointer size from the size of `long`, as libbpf does
+fn guess_ptr_sz(types: &[BtfType]) -> u32 {
+    types
+        .iter()
+        .find_map(|ty| match ty {
+            BtfType::Int(t)
+                if matches!(
+                    t.name,
+                    "long int" | "long unsigned int" | "long" | "unsigned long"
+                ) =>
+            {
+                Some(t.bits / 8)
+            }
+            _ => None,
+        })
+        .unwrap_or(8)
+}
@@ -0,0 +1,327 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::fmt;
+
+/// A loaded BTF. Names of the types borrow the bytes it was loaded from
+///
+/// A split BTF (like `/sys/kernel/btf/<module>`) also holds the types of its base BTF, so that type ids are the same as in the kernel. Its own types start from `start_id`
+#[derive(Debug, Clone)]
+pub struct Btf<'a> {
+    pub(super) types: Vec<BtfType<'a>>,
+    pub(super) ptr_sz: u32,
+    pub(super) start_id: u32,
+    /// String sections of the base BTFs and this BTF, in order
+    pub(super) strings: Vec<&'a [u8]>,
+}
+
+impl<'a> Btf<'a> {
+    /// Size of pointers in bytes
+    pub fn ptr_sz(&self) -> u32 {
+        self.ptr_sz
+    }
+    /// All types, indexed by the type id. Type id 0 is always `void`
+    pub fn types(&self) -> &[BtfType<'a>] {
+        &self.types
+    }
+    /// Get the type by its id. Panics if the id is out of range.
+    ///
+    /// Type ids referred by the loaded types are checked when loading, so they never panic
+    pub fn type_by_id(&self, type_id: u32) -> &BtfType<'a> {
+        &self.types[type_id as usize]
+    }
+    /// Count of types, including `void`
+    pub fn type_cnt(&self) -> u32 {
+        self.types.len() as u32
+    }
+    /// Id of the first type defined by this BTF. It's 1 unless it's a split BTF
+    pub fn start_id(&self) -> u32 {
+        self.start_id
+    }
+    /// If it's a split BTF, whose types before `start_id` come from the base BTF
+    pub fn is_split(&self) -> bool {
+        self.start_id > 1
+    }
+    /// Size of the type in bytes, following typedefs and modifiers.
+    ///
+    /// Sizes of the loaded types are checked when loading, so they never panic
+    pub fn get_size_of(&self, type_id: u32) -> u32 {
+        self.checked_size_of(type_id, 0)
+            .expect("Sizes of types are checked when loading")
+    }
+    /// Size of the type in bytes, or `None` if the type id is invalid, the size overflows, or typedefs and modifiers are nested deeper than `MAX_RESOLVE_DEPTH`
+    pub(super) fn checked_size_of(&self, type_id: u32, depth: u32) -> Option<u32> {
+        if depth > MAX_RESOLVE_DEPTH {
+            return None;
+        }
+        Some(match self.types.get(type_id as usize)? {
+            BtfType::Void => 0,
+            BtfType::Int(t) => t.bits.div_ceil(8),
+            BtfType::Ptr(_) => self.ptr_sz,
+            BtfType::Array(t) => t
+                .nelems
+                .checked_mul(self.checked_size_of(t.val_type_id, depth + 1)?)?,
+            BtfType::Struct(t) | BtfType::Union(t) => t.sz,
+            BtfType::Enum(t) => t.sz,
+            BtfType::Typedef(BtfTypedef { type_id, .. })
+            | BtfType::Volatile(BtfVolatile { type_id })
+            | BtfType::Const(BtfConst { type_id })
+            | BtfType::Restrict(BtfRestrict { type_id })
+            | BtfType::DeclTag(BtfDeclTag { type_id, .. })
+            | BtfType::TypeTag(BtfTypeTag { type_id, .. }) => {
+                self.checked_size_of(*type_id, depth + 1)?
+            }
+            BtfType::Datasec(t) => t.sz,
+            BtfType::Float(t) => t.sz,
+            BtfType::Fwd(_) | BtfType::Func(_) | BtfType::FuncProto(_) | BtfType::Var(_) => 0,
+        })
+    }
+}
+
+/// How deep typedefs, modifiers and arrays may nest, as libbpf does. Deeper nesting is most likely a loop
+pub const MAX_RESOLVE_DEPTH: u32 = 32;
+
+/// A BTF type. `BTF_KIND_ENUM` and `BTF_KIND_ENUM64` are both represented by `Enum`
+#[derive(Debug, Clone)]
+pub enum BtfType<'a> {
+    Void,
+    Int(BtfInt<'a>),
+    Ptr(BtfPtr),
+    Array(BtfArray),
+    Struct(BtfComposite<'a>),
+    Union(BtfComposite<'a>),
+    Enum(BtfEnum<'a>),
+    Fwd(BtfFwd<'a>),
+    Typedef(BtfTypedef<'a>),
+    Volatile(BtfVolatile),
+    Const(BtfConst),
+    Restrict(BtfRestrict),
+    Func(BtfFunc<'a>),
+    FuncProto(BtfFuncProto<'a>),
+    Var(BtfVar<'a>),
+    Datasec(BtfDatasec<'a>),
+    Float(BtfFloat<'a>),
+    DeclTag(BtfDeclTag<'a>),
+    TypeTag(BtfTypeTag<'a>),
+}
+
+impl<'a> BtfType<'a> {
+    /// Name of the type. Empty for anonymous types and the types without names
+    pub fn name(&self) -> &'a str {
+        match self {
+            BtfType::Int(t) => t.name,
+            BtfType::Struct(t) | BtfType::Union(t) => t.name,
+            BtfType::Enum(t) => t.name,
+            BtfType::Fwd(t) => t.name,
+            BtfType::Typedef(t) => t.name,
+            BtfType::Func(t) => t.name,
+            BtfType::Var(t) => t.name,
+            BtfType::Datasec(t) => t.name,
+            BtfType::Float(t) => t.name,
+            BtfType::DeclTag(t) => t.name,
+            BtfType::TypeTag(t) => t.name,
+            BtfType::Void
+            | BtfType::Ptr(_)
+            | BtfType::Array(_)
+            | BtfType::Volatile(_)
+            | BtfType::Const(_)
+            | BtfType::Restrict(_)
+            | BtfType::FuncProto(_) => "",
+        }
+    }
+    /// Name of the kind, like `STRUCT`
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            BtfType::Void => "VOID",
+            BtfType::Int(_) => "INT",
+            BtfType::Ptr(_) => "PTR",
+            BtfType::Array(_) => "ARRAY",
+            BtfType::Struct(_) => "STRUCT",
+            BtfType::Union(_) => "UNION",
+            BtfType::Enum(_) => "ENUM",
+            BtfType::Fwd(_) => "FWD",
+            BtfType::Typedef(_) => "TYPEDEF",
+            BtfType::Volatile(_) => "VOLATILE",
+            BtfType::Const(_) => "CONST",
+            BtfType::Restrict(_) => "RESTRICT",
+            BtfType::Func(_) => "FUNC",
+            BtfType::FuncProto(_) => "FUNC_PROTO",
+            BtfType::Var(_) => "VAR",
+            BtfType::Datasec(_) => "DATASEC",
+            BtfType::Float(_) => "FLOAT",
+            BtfType::DeclTag(_) => "DECL_TAG",
+            BtfType::TypeTag(_) => "TYPE_TAG",
+        }
+    }
+}
+
+impl fmt::Display for BtfType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}> '{}'", self.kind_name(), self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtfIntEncoding {
+    None,
+    Signed,
+    Char,
+    Bool,
+}
+
+impl fmt::Display for BtfIntEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BtfIntEncoding::None => write!(f, "none"),
+            BtfIntEncoding::Signed => write!(f, "signed"),
+            BtfIntEncoding::Char => write!(f, "char"),
+            BtfIntEncoding::Bool => write!(f, "bool"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfInt<'a> {
+    pub name: &'a str,
+    pub bits: u32,
+    pub offset: u32,
+    pub encoding: BtfIntEncoding,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfPtr {
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfArray {
+    pub nelems: u32,
+    pub idx_type_id: u32,
+    pub val_type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfMember<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+    pub bit_offset: u32,
+    /// Bits of a bitfield member, 0 if it's not a bitfield
+    pub bit_size: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfComposite<'a> {
+    pub is_struct: bool,
+    pub name: &'a str,
+    pub sz: u32,
+    pub members: Vec<BtfMember<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfEnumValue<'a> {
+    pub name: &'a str,
+    /// The value, sign-extended or zero-extended by the signedness of the enum
+    pub value: i128,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfEnum<'a> {
+    pub name: &'a str,
+    pub sz: u32,
+    /// Taken from the kind flag. Compilers not setting it produce unsigned enums
+    pub is_signed: bool,
+    pub values: Vec<BtfEnumValue<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtfFwdKind {
+    Struct,
+    Union,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfFwd<'a> {
+    pub name: &'a str,
+    pub kind: BtfFwdKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfTypedef<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfVolatile {
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfConst {
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfRestrict {
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfFunc<'a> {
+    pub name: &'a str,
+    pub proto_type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfFuncParam<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfFuncProto<'a> {
+    pub res_type_id: u32,
+    pub params: Vec<BtfFuncParam<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfVar<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfDatasecVar {
+    pub type_id: u32,
+    pub offset: u32,
+    pub sz: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfDatasec<'a> {
+    pub name: &'a str,
+    pub sz: u32,
+    pub vars: Vec<BtfDatasecVar>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfFloat<'a> {
+    pub name: &'a str,
+    pub sz: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfDeclTag<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+    /// Index of the member or parameter the tag is attached to, -1 for the type itself
+    pub comp_idx: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtfTypeTag<'a> {
+    pub name: &'a str,
+    pub type_id: u32,
+}
@@ -5,7 +5,7 @@
 //!
 use std::collections::HashMap;
 
-use btf::types::Btf;
+use crate::btf::types::Btf;
 
 pub(crate) struct SizeResolveCache<'a> {
     btf: &'a Btf<'a>,
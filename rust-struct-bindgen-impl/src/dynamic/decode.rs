@@ -0,0 +1,259 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use crate::btf::types::{Btf, BtfComposite, BtfEnum, BtfInt, BtfIntEncoding, BtfType};
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    cache::SizeResolveCache,
+    helper::{lookup_types, member_name},
+    types::{
+        array::StringPolicies,
+        bitfield::{resolve_bitfield, BitfieldKind},
+        enumeration::EnumKinds,
+    },
+    BindgenOptions, StringPolicy,
+};
+
+use super::{member_range, read_bitfield, TypeRef, Value};
+
+/// Decode the bytes as the given type, with the default options. The length of `data` must be the size of the type
+pub fn decode<'a>(btf: &Btf, ty: impl Into<TypeRef<'a>>, data: &[u8]) -> Result<Value> {
+    decode_with_options(btf, ty, data, &BindgenOptions::default())
+}
+
+/// Decode the bytes as the given type, agreeing with the code generated with the same options. The length of `data` must be the size of the type
+pub fn decode_with_options<'a>(
+    btf: &Btf,
+    ty: impl Into<TypeRef<'a>>,
+    data: &[u8],
+    options: &BindgenOptions,
+) -> Result<Value> {
+    let ty_id = ty.into().resolve(btf)?;
+    let mut decoder = Decoder {
+        btf,
+        size_resolver: SizeResolveCache::new(btf),
+        strings: StringPolicies::new(options)?,
+        enums: EnumKinds::new(options)?,
+    };
+    let size = decoder.size_resolver.resolve(ty_id);
+    if data.len() != size {
+        bail!("Expected a slice with {} bytes, found {}", size, data.len());
+    }
+    decoder.decode_type(ty_id, data)
+}
+
+struct Decoder<'a> {
+    btf: &'a Btf<'a>,
+    size_resolver: SizeResolveCache<'a>,
+    strings: StringPolicies,
+    enums: EnumKinds,
+}
+
+impl Decoder<'_> {
+    fn decode_type(&mut self, ty_id: u32, b: &[u8]) -> Result<Value> {
+        let btf = self.btf;
+        let ty_id = lookup_types(btf, ty_id)?;
+        match btf.type_by_id(ty_id) {
+            BtfType::Int(btf_int) => decode_int(btf_int, b),
+            BtfType::Float(btf_float) => Ok(Value::Float(match btf_float.sz {
+                4 => f32::from_ne_bytes(b.try_into().unwrap()) as f64,
+                8 => f64::from_ne_bytes(b.try_into().unwrap()),
+                s => bail!("Unsupported float size: {}", s),
+            })),
+            BtfType::Ptr(_) => Ok(Value::Pointer(match b.len() {
+                4 => u32::from_ne_bytes(b.try_into().unwrap()) as u64,
+                8 => u64::from_ne_bytes(b.try_into().unwrap()),
+                s => bail!("Unsupported pointer size: {}", s),
+            })),
+            BtfType::Enum(btf_enum) => decode_enum(btf_enum, &self.enums, b),
+            BtfType::Array(array) => {
+                let elem_ty = lookup_types(btf, array.val_type_id)?;
+                if let Some(policy) = self.strings.array_policy(btf, array) {
+                    decode_string(policy, b)
+                } else {
+                    let elem_size = self.size_resolver.resolve(elem_ty);
+                    let mut result = vec![];
+                    for i in 0..array.nelems as usize {
+                        result.push(
+                            self.decode_type(elem_ty, &b[i * elem_size..(i + 1) * elem_size])
+                                .with_context(|| anyhow!("Failed to decode element {}", i))?,
+                        );
+                    }
+                    Ok(Value::Array(result))
+                }
+            }
+            BtfType::Struct(comp) => {
+                let mut result = vec![];
+                for (idx, member) in comp.members.iter().enumerate() {
+                    let name = member_name(member.name, idx);
+                    let value = self
+                        .decode_member(comp, idx, b)
+                        .with_context(|| anyhow!("Failed to decode field `{}`", name))?;
+                    result.push((name, value));
+                }
+                Ok(Value::Struct(result))
+            }
+            BtfType::Union(comp) => {
+                let mut result = vec![];
+                for idx in 0..comp.members.len() {
+                    // Members placed out of the union are errors of the BTF, while members which can't be decoded are errors of the data
+                    member_range(btf, &mut self.size_resolver, comp, idx, b.len())?;
+                    let value = self
+                        .decode_member(comp, idx, b)
+                        .unwrap_or_else(|e| Value::Invalid(format!("{:#}", e)));
+                    result.push((member_name(comp.members[idx].name, idx), value));
+                }
+                Ok(Value::Union(result))
+            }
+            s => bail!("Unsupported type: {}", s),
+        }
+    }
+    /// Decode a member of a struct or union from the bytes of the whole struct or union
+    fn decode_member(&mut self, comp: &BtfComposite, idx: usize, b: &[u8]) -> Result<Value> {
+        let btf = self.btf;
+        let member = &comp.members[idx];
+        let range = member_range(btf, &mut self.size_resolver, comp, idx, b.len())?;
+        if let Some(bitfield) = resolve_bitfield(btf, member)? {
+            let raw = read_bitfield(b, bitfield.bit_offset as usize, bitfield.bit_size as usize);
+            return match bitfield.kind {
+                BitfieldKind::Bool => Ok(Value::Bool(raw != 0)),
+                BitfieldKind::Int { signed } => Ok(Value::Int(if signed {
+                    let shift = 128 - bitfield.bit_size;
+                    ((raw << shift) as i128) >> shift
+                } else {
+                    raw as i128
+                })),
+                BitfieldKind::Enum {
+                    type_id,
+                    size,
+                    signed,
+                } => {
+                    let raw = if signed {
+                        let shift = 128 - bitfield.bit_size;
+                        (((raw << shift) as i128) >> shift) as u128
+                    } else {
+                        raw
+                    };
+                    let bytes = raw.to_ne_bytes();
+                    let bytes = if cfg!(target_endian = "little") {
+                        &bytes[..size]
+                    } else {
+                        &bytes[16 - size..]
+                    };
+                    self.decode_type(type_id, bytes)
+                }
+            };
+        }
+        let real_ty = lookup_types(btf, member.type_id)?;
+        if let Some(policy) = self
+            .strings
+            .member_policy(btf, comp.name, member.name, real_ty)
+        {
+            return decode_string(policy, &b[range]);
+        }
+        self.decode_type(member.type_id, &b[range])
+    }
+}
+
+/// Decode a char array by the string policy, like the generated code. Policies representing it by bytes yield arrays of the byte values
+fn decode_string(policy: StringPolicy, b: &[u8]) -> Result<Value> {
+    let nul = b.iter().position(|v| *v == 0);
+    let trimmed = &b[..nul.unwrap_or(b.len())];
+    let bytes = |b: &[u8]| Value::Array(b.iter().map(|v| Value::Int(*v as i128)).collect());
+    let utf8 = |b: &[u8]| {
+        String::from_utf8(b.to_vec())
+            .map_err(|e| anyhow!("Invalid utf8 strings when deserializling: {}", e))
+    };
+    Ok(match policy {
+        // A zero-length array has no room for the NUL byte
+        StringPolicy::Strict if !b.is_empty() => {
+            let idx = nul.ok_or_else(|| anyhow!("zero byte not found when deserializing"))?;
+            Value::String(utf8(&b[..idx])?)
+        }
+        StringPolicy::Strict | StringPolicy::NulOptional => Value::String(utf8(trimmed)?),
+        StringPolicy::Lossy => Value::String(String::from_utf8_lossy(trimmed).into_owned()),
+        StringPolicy::CString | StringPolicy::Bytes => bytes(trimmed),
+        StringPolicy::Raw => bytes(b),
+    })
+}
+
+fn decode_int(btf_int: &BtfInt, b: &[u8]) -> Result<Value> {
+    if btf_int.bits == 0 || btf_int.bits > 128 {
+        bail!("Unsupported integer of {} bits", btf_int.bits);
+    }
+    if (btf_int.offset + btf_int.bits) as usize > b.len() * 8 {
+        bail!(
+            "Integer of {} bits at bit offset {} is out of its {} bytes",
+            btf_int.bits,
+            btf_int.offset,
+            b.len()
+        );
+    }
+    if btf_int.bits % 8 != 0 || btf_int.offset != 0 {
+        let raw = read_bitfield(b, btf_int.offset as usize, btf_int.bits as usize);
+        return Ok(match btf_int.encoding {
+            BtfIntEncoding::Bool => Value::Bool(raw != 0),
+            BtfIntEncoding::Signed => {
+                let shift = 128 - btf_int.bits;
+                Value::Int(((raw << shift) as i128) >> shift)
+            }
+            _ => Value::Int(raw as i128),
+        });
+    }
+    let signed = matches!(btf_int.encoding, BtfIntEncoding::Signed);
+    Ok(match (b.len(), signed) {
+        (1, _) if matches!(btf_int.encoding, BtfIntEncoding::Bool) => Value::Bool(b[0] != 0),
+        (1, true) => Value::Int(i8::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (1, false) => Value::Int(u8::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (2, true) => Value::Int(i16::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (2, false) => Value::Int(u16::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (4, true) => Value::Int(i32::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (4, false) => Value::Int(u32::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (8, true) => Value::Int(i64::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (8, false) => Value::Int(u64::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (16, true) => Value::Int(i128::from_ne_bytes(b.try_into().unwrap())),
+        (16, false) => Value::Int(u128::from_ne_bytes(b.try_into().unwrap()) as i128),
+        (s, _) => bail!("Unsupported integer size {}", s),
+    })
+}
+
+/// Decode an enum to the name of its enumerator. Bit flags are named by the set flags, and values of open enums not defined in the BTF are integers
+fn decode_enum(btf_enum: &BtfEnum, enums: &EnumKinds, b: &[u8]) -> Result<Value> {
+    let value = match (b.len(), btf_enum.is_signed) {
+        (1, true) => i8::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (1, false) => u8::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (2, true) => i16::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (2, false) => u16::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (4, true) => i32::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (4, false) => u32::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (8, true) => i64::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (8, false) => u64::from_ne_bytes(b.try_into().unwrap()) as i128,
+        (s, _) => bail!("Unsupported enum size `{}` in enum `{}`", s, btf_enum.name),
+    };
+    let name = match btf_enum.values.iter().find(|v| v.value == value) {
+        Some(v) => v.name.to_string(),
+        None if enums.is_bitflags(btf_enum) => bitflags_name(btf_enum, value),
+        None if enums.is_open(btf_enum) => return Ok(Value::Int(value)),
+        None => bail!("Invalid enum value {} for enum {}", value, btf_enum.name),
+    };
+    Ok(Value::Enum { name, value })
+}
+
+/// Names of the set flags joined by ` | `, followed by the remaining bits in hex, like the generated `Debug`
+fn bitflags_name(btf_enum: &BtfEnum, value: i128) -> String {
+    let mut names = vec![];
+    let mut rest = value;
+    for v in btf_enum.values.iter() {
+        if v.value != 0 && (value & v.value) == v.value && (rest & v.value) != 0 {
+            names.push(v.name.to_string());
+            rest &= !v.value;
+        }
+    }
+    if rest != 0 || names.is_empty() {
+        names.push(format!("{:#x}", rest));
+    }
+    names.join(" | ")
+}
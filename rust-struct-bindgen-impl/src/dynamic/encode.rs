@@ -0,0 +1,362 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use crate::btf::types::{Btf, BtfComposite, BtfEnum, BtfInt, BtfIntEncoding, BtfType};
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    cache::SizeResolveCache,
+    helper::{lookup_types, member_name},
+    types::{
+        array::StringPolicies,
+        bitfield::{resolve_bitfield, BitfieldKind},
+        enumeration::EnumKinds,
+    },
+    BindgenOptions, StringPolicy,
+};
+
+use super::{member_range, write_bitfield, TypeRef, Value};
+
+/// Encode the value as the given type with the default options, producing the same bytes as the generated serializing functions.
+///
+/// Missing struct fields and array elements are filled with zero, and `Invalid` members are skipped. A union is encoded from one member; when several are given, they must describe the same bytes, as the members of a decoded union do. Errors are prefixed with the path of the offending field, e.g. `S.arr[1].name`
+pub fn encode<'a>(btf: &Btf, ty: impl Into<TypeRef<'a>>, value: &Value) -> Result<Vec<u8>> {
+    encode_with_options(btf, ty, value, &BindgenOptions::default())
+}
+
+/// Encode the value as the given type, producing the same bytes as the code generated with the same options
+pub fn encode_with_options<'a>(
+    btf: &Btf,
+    ty: impl Into<TypeRef<'a>>,
+    value: &Value,
+    options: &BindgenOptions,
+) -> Result<Vec<u8>> {
+    let ty_id = ty.into().resolve(btf)?;
+    let mut encoder = Encoder {
+        btf,
+        size_resolver: SizeResolveCache::new(btf),
+        strings: StringPolicies::new(options)?,
+        enums: EnumKinds::new(options)?,
+    };
+    let mut result = vec![0u8; encoder.size_resolver.resolve(ty_id)];
+    let root = match btf.type_by_id(ty_id).name() {
+        "" => "value",
+        s => s,
+    };
+    encoder.encode_type(ty_id, value, &mut result, root)?;
+    Ok(result)
+}
+
+struct Encoder<'a> {
+    btf: &'a Btf<'a>,
+    size_resolver: SizeResolveCache<'a>,
+    strings: StringPolicies,
+    enums: EnumKinds,
+}
+
+impl Encoder<'_> {
+    /// Encode the value into `out`, whose length is the size of the type
+    fn encode_type(&mut self, ty_id: u32, value: &Value, out: &mut [u8], path: &str) -> Result<()> {
+        let btf = self.btf;
+        let ty_id = lookup_types(btf, ty_id)?;
+        match btf.type_by_id(ty_id) {
+            BtfType::Int(btf_int) => encode_int(btf_int, value, out, path),
+            BtfType::Float(btf_float) => {
+                let v = match value {
+                    Value::Float(v) => *v,
+                    Value::Int(v) => *v as f64,
+                    s => bail!("{}: Expected a float, found {:?}", path, s),
+                };
+                match btf_float.sz {
+                    4 => out.copy_from_slice(&(v as f32).to_ne_bytes()),
+                    8 => out.copy_from_slice(&v.to_ne_bytes()),
+                    s => bail!("{}: Unsupported float size: {}", path, s),
+                }
+                Ok(())
+            }
+            BtfType::Ptr(_) => {
+                let v = match value {
+                    Value::Pointer(v) => *v as i128,
+                    Value::Int(v) => *v,
+                    s => bail!("{}: Expected a pointer, found {:?}", path, s),
+                };
+                write_int(v, false, out, path)
+            }
+            BtfType::Enum(btf_enum) => {
+                let v = enum_value(btf_enum, &self.enums, value, path)?;
+                write_int(v, btf_enum.is_signed, out, path)
+            }
+            BtfType::Array(array) => {
+                let elem_ty = lookup_types(btf, array.val_type_id)?;
+                if let Some(policy) = self.strings.array_policy(btf, array) {
+                    return encode_string(policy, value, out, path);
+                }
+                let Value::Array(values) = value else {
+                    bail!("{}: Expected an array, found {:?}", path, value);
+                };
+                if values.len() > array.nelems as usize {
+                    bail!(
+                        "{}: Too many elements, at most {} is allowed",
+                        path,
+                        array.nelems
+                    );
+                }
+                let elem_size = self.size_resolver.resolve(elem_ty);
+                for (i, v) in values.iter().enumerate() {
+                    self.encode_type(
+                        elem_ty,
+                        v,
+                        &mut out[i * elem_size..(i + 1) * elem_size],
+                        &format!("{}[{}]", path, i),
+                    )?;
+                }
+                Ok(())
+            }
+            BtfType::Struct(comp) | BtfType::Union(comp) => {
+                let fields = match value {
+                    Value::Struct(fields) | Value::Union(fields) => fields,
+                    s => bail!("{}: Expected a struct or union, found {:?}", path, s),
+                };
+                let mut members = vec![];
+                for (name, v) in fields.iter() {
+                    if matches!(v, Value::Invalid(_)) {
+                        continue;
+                    }
+                    let idx = (0..comp.members.len())
+                        .find(|idx| &member_name(comp.members[*idx].name, *idx) == name)
+                        .ok_or_else(|| anyhow!("{}: No member named `{}`", path, name))?;
+                    members.push((idx, v, format!("{}.{}", path, name)));
+                }
+                if !comp.is_struct && members.len() > 1 {
+                    return self.encode_union_members(comp, &members, out, path);
+                }
+                for (idx, v, path) in members.iter() {
+                    self.encode_member(comp, *idx, v, out, path)?;
+                }
+                Ok(())
+            }
+            s => bail!("{}: Unsupported type: {}", path, s),
+        }
+    }
+    /// Encode a union given several members, like a decoded union, from only one of them. It's the first member which writing any other given member on top of leaves the bytes unchanged,
+    /// so that the members must describe the same bytes. Otherwise the result would depend on the order of the members
+    fn encode_union_members(
+        &mut self,
+        comp: &BtfComposite,
+        members: &[(usize, &Value, String)],
+        out: &mut [u8],
+        path: &str,
+    ) -> Result<()> {
+        for (idx, v, member_path) in members.iter() {
+            let mut bytes = out.to_vec();
+            self.encode_member(comp, *idx, v, &mut bytes, member_path)?;
+            let mut holds_others = true;
+            for (other_idx, other, other_path) in members.iter() {
+                let mut other_bytes = bytes.clone();
+                self.encode_member(comp, *other_idx, other, &mut other_bytes, other_path)?;
+                if other_bytes != bytes {
+                    holds_others = false;
+                    break;
+                }
+            }
+            if holds_others {
+                out.copy_from_slice(&bytes);
+                return Ok(());
+            }
+        }
+        bail!(
+            "{}: Members {} of the union describe different bytes, give only one of them",
+            path,
+            members
+                .iter()
+                .map(|(idx, _, _)| format!("`{}`", member_name(comp.members[*idx].name, *idx)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+    /// Encode a member of a struct or union into the bytes of the whole struct or union
+    fn encode_member(
+        &mut self,
+        comp: &BtfComposite,
+        idx: usize,
+        value: &Value,
+        out: &mut [u8],
+        path: &str,
+    ) -> Result<()> {
+        let btf = self.btf;
+        let member = &comp.members[idx];
+        let range = member_range(btf, &mut self.size_resolver, comp, idx, out.len())?;
+        if let Some(bitfield) = resolve_bitfield(btf, member)? {
+            let raw = match bitfield.kind {
+                BitfieldKind::Bool | BitfieldKind::Int { .. } => {
+                    let signed = matches!(bitfield.kind, BitfieldKind::Int { signed: true });
+                    bits_of_int(int_value(value, path)?, bitfield.bit_size, signed, path)?
+                }
+                BitfieldKind::Enum { type_id, .. } => {
+                    let BtfType::Enum(btf_enum) = btf.type_by_id(type_id) else {
+                        bail!("{}: Invalid enum type {}", path, type_id);
+                    };
+                    let v = enum_value(btf_enum, &self.enums, value, path)?;
+                    (v as u128) & ((1u128 << bitfield.bit_size) - 1)
+                }
+            };
+            write_bitfield(
+                out,
+                bitfield.bit_offset as usize,
+                bitfield.bit_size as usize,
+                raw,
+            );
+            return Ok(());
+        }
+        let real_ty = lookup_types(btf, member.type_id)?;
+        if let Some(policy) = self
+            .strings
+            .member_policy(btf, comp.name, member.name, real_ty)
+        {
+            return encode_string(policy, value, &mut out[range], path);
+        }
+        self.encode_type(member.type_id, value, &mut out[range], path)
+    }
+}
+
+/// Encode a string, or an array of byte values, into a char array by the string policy, like the generated code. The rest of the array is filled with zero
+fn encode_string(policy: StringPolicy, value: &Value, out: &mut [u8], path: &str) -> Result<()> {
+    let bytes = match value {
+        Value::String(s) => s.as_bytes().to_vec(),
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let v = int_value(v, &format!("{}[{}]", path, i))?;
+                // Both signed and unsigned chars are accepted
+                u8::try_from(v)
+                    .or_else(|_| i8::try_from(v).map(|v| v as u8))
+                    .map_err(|_| anyhow!("{}[{}]: Value {} doesn't fit in a byte", path, i, v))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        s => bail!("{}: Expected a string, found {:?}", path, s),
+    };
+    // Strict strings and C strings keep room for the NUL byte
+    let max_len = match policy {
+        StringPolicy::Strict | StringPolicy::CString => out.len().saturating_sub(1),
+        _ => out.len(),
+    };
+    if bytes.len() > max_len {
+        bail!(
+            "{}: String is too long! only {} bytes is allowed",
+            path,
+            max_len
+        );
+    }
+    out[..bytes.len()].copy_from_slice(&bytes);
+    out[bytes.len()..].fill(0);
+    Ok(())
+}
+
+fn int_value(value: &Value, path: &str) -> Result<i128> {
+    match value {
+        Value::Int(v) => Ok(*v),
+        Value::Bool(v) => Ok(*v as i128),
+        // Integers not fitting in 64 bits are usually carried as strings in text formats
+        Value::String(s) => s
+            .parse()
+            .map_err(|_| anyhow!("{}: Expected an integer, found {:?}", path, s)),
+        s => bail!("{}: Expected an integer, found {:?}", path, s),
+    }
+}
+
+/// Check that the value fits in the given bits, and return its two's complement representation
+fn bits_of_int(v: i128, bits: u32, signed: bool, path: &str) -> Result<u128> {
+    let (min, max) = if signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else if bits == 128 {
+        (0, i128::MAX)
+    } else {
+        (0, (1i128 << bits) - 1)
+    };
+    if v < min || v > max {
+        bail!(
+            "{}: Value {} doesn't fit in an integer with {} bits",
+            path,
+            v,
+            bits
+        );
+    }
+    Ok(if bits == 128 {
+        v as u128
+    } else {
+        (v as u128) & ((1u128 << bits) - 1)
+    })
+}
+
+fn write_int(v: i128, signed: bool, out: &mut [u8], path: &str) -> Result<()> {
+    let raw = bits_of_int(v, out.len() as u32 * 8, signed, path)?;
+    let bytes = raw.to_ne_bytes();
+    if cfg!(target_endian = "little") {
+        out.copy_from_slice(&bytes[..out.len()]);
+    } else {
+        out.copy_from_slice(&bytes[16 - out.len()..]);
+    }
+    Ok(())
+}
+
+fn encode_int(btf_int: &BtfInt, value: &Value, out: &mut [u8], path: &str) -> Result<()> {
+    let signed = matches!(btf_int.encoding, BtfIntEncoding::Signed);
+    let v = int_value(value, path)?;
+    if btf_int.bits % 8 != 0 || btf_int.offset != 0 {
+        let raw = bits_of_int(v, btf_int.bits, signed, path)?;
+        write_bitfield(out, btf_int.offset as usize, btf_int.bits as usize, raw);
+        return Ok(());
+    }
+    write_int(v, signed, out, path)
+}
+
+/// Resolve the value of an enum from its name, or check that the value is one of the enumerators.
+/// Bit flags take any value, and names or hex bits joined by `|` as decoded. Open enums take any integer
+fn enum_value(btf_enum: &BtfEnum, enums: &EnumKinds, value: &Value, path: &str) -> Result<i128> {
+    if enums.is_bitflags(btf_enum) {
+        return match value {
+            Value::Enum { value, .. } | Value::Int(value) => Ok(*value),
+            Value::String(names) => names.split('|').map(str::trim).try_fold(0, |acc, name| {
+                if let Some(hex) = name.strip_prefix("0x") {
+                    let v = i128::from_str_radix(hex, 16)
+                        .map_err(|e| anyhow!("{}: Invalid bits {}: {}", path, name, e))?;
+                    return Ok(acc | v);
+                }
+                let v = btf_enum
+                    .values
+                    .iter()
+                    .find(|v| v.name == name)
+                    .ok_or_else(|| {
+                        anyhow!("{}: Unknown flag {} of enum {}", path, name, btf_enum.name)
+                    })?;
+                Ok(acc | v.value)
+            }),
+            s => bail!("{}: Expected an enum, found {:?}", path, s),
+        };
+    }
+    if let (true, Value::Int(v)) = (enums.is_open(btf_enum), value) {
+        return Ok(*v);
+    }
+    let (found, given) = match value {
+        Value::Enum { name, .. } | Value::String(name) => (
+            btf_enum.values.iter().find(|v| v.name == name),
+            name.to_string(),
+        ),
+        Value::Int(v) => (
+            btf_enum.values.iter().find(|e| e.value == *v),
+            v.to_string(),
+        ),
+        s => bail!("{}: Expected an enum, found {:?}", path, s),
+    };
+    found.map(|v| v.value).ok_or_else(|| {
+        anyhow!(
+            "{}: Invalid enum value {} for enum {}",
+            path,
+            given,
+            btf_enum.name
+        )
+    })
+}
@@ -0,0 +1,171 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//!
+//! # Dynamic (de)serializing
+//! Interpret bytes with a BTF type picked at runtime, without generating and compiling rust code, and encode values back to bytes.
+//!
+//! The layout rules are the same as the generated code: typedefs and qualifiers are resolved, char arrays follow the string policies of the `BindgenOptions` given to `decode_with_options` and `encode_with_options`, enums are decoded to their names, and bitfields are extracted from their storage units.
+use std::ops::Range;
+
+use crate::{
+    btf::types::{Btf, BtfComposite, BtfType},
+    cache::SizeResolveCache,
+    types::bitfield::resolve_bitfield,
+};
+use anyhow::{anyhow, bail, Result};
+
+mod decode;
+mod encode;
+
+pub use decode::{decode, decode_with_options};
+pub use encode::{encode, encode_with_options};
+
+/// A decoded value of a BTF type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Members of a struct, in declaration order. Anonymous members are named `anon_XX`, where `XX` is the member index
+    Struct(Vec<(String, Value)>),
+    /// Every member of a union, decoded from the bytes of the union. Members which can't be decoded from them are `Invalid`
+    Union(Vec<(String, Value)>),
+    Array(Vec<Value>),
+    Int(i128),
+    Bool(bool),
+    Float(f64),
+    /// An enum value, with the name of the matched enumerator
+    Enum {
+        name: String,
+        value: i128,
+    },
+    /// A char array represented by text. Char arrays represented by bytes (the `cstring`, `bytes` and `raw` string policies) are arrays of the byte values
+    String(String),
+    Pointer(u64),
+    /// A union member which can't be decoded from the bytes of the union, with the reason. It's skipped when encoding
+    Invalid(String),
+}
+
+/// Refer to a BTF type, either by its id or by its name
+#[derive(Debug, Clone, Copy)]
+pub enum TypeRef<'a> {
+    Id(u32),
+    Name(&'a str),
+}
+
+impl From<u32> for TypeRef<'_> {
+    fn from(value: u32) -> Self {
+        TypeRef::Id(value)
+    }
+}
+
+impl<'a> From<&'a str> for TypeRef<'a> {
+    fn from(value: &'a str) -> Self {
+        TypeRef::Name(value)
+    }
+}
+
+impl TypeRef<'_> {
+    /// Resolve the type id. Names are looked up in structs, unions, enums, typedefs, ints and floats; the first match wins
+    pub fn resolve(&self, btf: &Btf) -> Result<u32> {
+        match *self {
+            TypeRef::Id(id) => {
+                if id as usize >= btf.types().len() {
+                    bail!("Invalid type id: {}", id);
+                }
+                Ok(id)
+            }
+            TypeRef::Name(name) => btf
+                .types()
+                .iter()
+                .position(|ty| {
+                    matches!(
+                        ty,
+                        BtfType::Struct(_)
+                            | BtfType::Union(_)
+                            | BtfType::Enum(_)
+                            | BtfType::Typedef(_)
+                            | BtfType::Int(_)
+                            | BtfType::Float(_)
+                    ) && ty.name() == name
+                })
+                .map(|v| v as u32)
+                .ok_or_else(|| anyhow!("Type `{}` not found", name)),
+        }
+    }
+}
+
+/// The range of bytes of the struct or union holding the member. Fails if the BTF places the member out of the `len` bytes of the struct or union
+pub(crate) fn member_range(
+    btf: &Btf,
+    size_resolver: &mut SizeResolveCache,
+    comp: &BtfComposite,
+    idx: usize,
+    len: usize,
+) -> Result<Range<usize>> {
+    let member = &comp.members[idx];
+    let range = match resolve_bitfield(btf, member)? {
+        Some(bitfield) => {
+            (bitfield.bit_offset / 8) as usize
+                ..(bitfield.bit_offset + bitfield.bit_size).div_ceil(8) as usize
+        }
+        None => {
+            let offset = (member.bit_offset / 8) as usize;
+            offset..offset + size_resolver.resolve(member.type_id)
+        }
+    };
+    if range.end > len {
+        bail!(
+            "Member `{}` at bytes {}..{} is out of the {} bytes of `{}`",
+            member.name,
+            range.start,
+            range.end,
+            len,
+            comp.name
+        );
+    }
+    Ok(range)
+}
+
+/// Read a bitfield in the same way as the `read_bitfield` in the generated code
+pub(crate) fn read_bitfield(b: &[u8], bit_offset: usize, bit_size: usize) -> u128 {
+    let start = bit_offset / 8;
+    let end = (bit_offset + bit_size).div_ceil(8);
+    let mut val = 0u128;
+    let shift = if cfg!(target_endian = "little") {
+        for (i, v) in b[start..end].iter().enumerate() {
+            val |= (*v as u128) << (i * 8);
+        }
+        bit_offset % 8
+    } else {
+        for v in b[start..end].iter() {
+            val = (val << 8) | (*v as u128);
+        }
+        (end - start) * 8 - bit_offset % 8 - bit_size
+    };
+    (val >> shift) & ((1u128 << bit_size) - 1)
+}
+
+/// Write a bitfield in the same way as the `write_bitfield` in the generated code
+#[allow(clippy::needless_range_loop)]
+pub(crate) fn write_bitfield(b: &mut [u8], bit_offset: usize, bit_size: usize, v: u128) {
+    let start = bit_offset / 8;
+    let end = (bit_offset + bit_size).div_ceil(8);
+    let shift = if cfg!(target_endian = "little") {
+        bit_offset % 8
+    } else {
+        (end - start) * 8 - bit_offset % 8 - bit_size
+    };
+    let mask = ((1u128 << bit_size) - 1) << shift;
+    let v = (v << shift) & mask;
+    for i in start..end {
+        let byte_shift = if cfg!(target_endian = "little") {
+            (i - start) * 8
+        } else {
+            (end - i - 1) * 8
+        };
+        let byte_mask = (mask >> byte_shift) as u8;
+        b[i] = (b[i] & !byte_mask) | ((v >> byte_shift) as u8 & byte_mask);
+    }
+}
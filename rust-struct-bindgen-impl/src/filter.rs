@@ -0,0 +1,112 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::collections::HashSet;
+
+use crate::btf::types::{Btf, BtfConst, BtfRestrict, BtfType, BtfTypeTag, BtfTypedef, BtfVolatile};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use crate::BindgenOptions;
+
+/// Select which types will be generated.
+///
+/// Root types are the named structs, unions, enums and typedefs matching any allowlist pattern (or all of them if there is no allowlist pattern), and not matching any blocklist pattern. For a split BTF without allowlist patterns, only the types defined by the split BTF itself are roots, so that types of the base BTF are generated only if they are referred to. The generated code contains the root types and everything they transitively refer to, except pointees, since pointers are represented as plain integers.
+///
+/// Patterns must match the whole type name. A blocklisted type is still generated if a root type refers to it, but it gets no top level alias.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TypeFilter {
+    allowlist: NamePatterns,
+    blocklist: NamePatterns,
+}
+
+impl TypeFilter {
+    pub(crate) fn new(options: &BindgenOptions) -> Result<Self> {
+        Ok(Self {
+            allowlist: NamePatterns::new(&options.allowlist_type)?,
+            blocklist: NamePatterns::new(&options.blocklist_type)?,
+        })
+    }
+    /// If the filter keeps every type
+    fn is_empty(&self) -> bool {
+        self.allowlist.is_empty() && self.blocklist.is_empty()
+    }
+    /// Ids of the types which could be roots
+    fn candidates(&self, btf: &Btf) -> std::ops::Range<u32> {
+        if btf.is_split() && self.allowlist.is_empty() {
+            btf.start_id()..btf.type_cnt()
+        } else {
+            0..btf.type_cnt()
+        }
+    }
+    /// If the name matches any blocklist pattern
+    pub(crate) fn is_blocked(&self, name: &str) -> bool {
+        self.blocklist.is_match(name)
+    }
+    fn is_root(&self, name: &str) -> bool {
+        !name.is_empty()
+            && (self.allowlist.is_empty() || self.allowlist.is_match(name))
+            && !self.is_blocked(name)
+    }
+    /// Collect the ids of the types to generate. Returns `None` if every type should be generated
+    pub(crate) fn selected_types(&self, btf: &Btf) -> Option<HashSet<u32>> {
+        if self.is_empty() && !btf.is_split() {
+            return None;
+        }
+        let mut selected = HashSet::new();
+        let mut stack = self
+            .candidates(btf)
+            .filter(|id| {
+                let ty = btf.type_by_id(*id);
+                matches!(
+                    ty,
+                    BtfType::Struct(_) | BtfType::Union(_) | BtfType::Enum(_) | BtfType::Typedef(_)
+                ) && self.is_root(ty.name())
+            })
+            .collect::<Vec<_>>();
+        while let Some(ty_id) = stack.pop() {
+            if !selected.insert(ty_id) {
+                continue;
+            }
+            match btf.type_by_id(ty_id) {
+                BtfType::Struct(comp) | BtfType::Union(comp) => {
+                    stack.extend(comp.members.iter().map(|v| v.type_id))
+                }
+                BtfType::Array(array) => stack.push(array.val_type_id),
+                BtfType::Typedef(BtfTypedef { type_id, .. })
+                | BtfType::Const(BtfConst { type_id })
+                | BtfType::Restrict(BtfRestrict { type_id })
+                | BtfType::Volatile(BtfVolatile { type_id })
+                | BtfType::TypeTag(BtfTypeTag { type_id, .. }) => stack.push(*type_id),
+                _ => {}
+            }
+        }
+        Some(selected)
+    }
+}
+
+/// Regexes of type names given by an option. Patterns must match the whole name
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NamePatterns(Vec<Regex>);
+
+impl NamePatterns {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .with_context(|| anyhow!("Invalid type pattern `{}`", pattern))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// If the name matches any pattern
+    pub(crate) fn is_match(&self, name: &str) -> bool {
+        self.0.iter().any(|r| r.is_match(name))
+    }
+}
@@ -3,16 +3,15 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
+use crate::btf::types::{
+    Btf, BtfConst, BtfRestrict, BtfType, BtfTypeTag, BtfTypedef, BtfVolatile, MAX_RESOLVE_DEPTH,
+};
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Result;
-use btf::types::{Btf, BtfConst, BtfRestrict, BtfType, BtfVolatile};
-use faerie::ArtifactBuilder;
-use faerie::Decl;
-use faerie::SectionKind;
+use object::ElfFile;
 use proc_macro2::{Ident, Span};
 use std::fmt::Display;
-use std::str::FromStr;
-use target_lexicon::triple;
 #[inline]
 /// Generate a type name for the specified type id
 pub(crate) fn ty_name(ty_id: impl Display) -> String {
@@ -37,36 +36,118 @@ pub(crate) fn func_names_ident(ty_id: impl Display) -> (Ident, Ident) {
         Ident::new(&ser, Span::call_site()),
     )
 }
-/// Lookup a type over const/restrict/volatile attributes
+#[inline]
+/// Generate the name of the encoding function, which writes into a caller provided buffer, from the name of the serializing function
+/// e.g `serialize_btf_type_1` -> `encode_btf_type_1`
+pub(crate) fn encode_func_ident(ser_func: &Ident) -> Ident {
+    let ser_func = ser_func.to_string();
+    Ident::new(
+        &format!("encode_{}", ser_func.trim_start_matches("serialize_")),
+        Span::call_site(),
+    )
+}
+#[inline]
+/// Generate a name for a struct or union member. Anonymous members are named after their index
+pub(crate) fn member_name(name: &str, idx: usize) -> String {
+    if name.is_empty() {
+        format!("anon_{}", idx)
+    } else {
+        name.to_string()
+    }
+}
+/// Check whether the name could be used as a top level type alias.
+/// Rust keywords and names of rust builtin types (e.g. `typedef __u8 u8`) are rejected
+pub(crate) fn is_valid_alias_name(name: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+        "f32", "f64", "bool", "char", "str", "Self", "String", "Vec", "Result", "Option", "Box",
+    ];
+    !name.is_empty() && !RESERVED.contains(&name) && syn::parse_str::<Ident>(name).is_ok()
+}
+/// Lookup a type over typedef/const/restrict/volatile/type tag attributes.
+/// Fails if they are nested deeper than `MAX_RESOLVE_DEPTH`, which is most likely a loop
 pub(crate) fn lookup_types(btf: &Btf, ty_id: u32) -> Result<u32> {
-    let result = match btf
-        .types()
-        .get(ty_id as usize)
-        .ok_or_else(|| anyhow!("Invalid type: {}", ty_id))?
-    {
-        BtfType::Typedef(btf_typedef) => lookup_types(btf, btf_typedef.type_id)?,
-        BtfType::Const(BtfConst { type_id })
-        | BtfType::Restrict(BtfRestrict { type_id })
-        | BtfType::Volatile(BtfVolatile { type_id }) => lookup_types(btf, *type_id)?,
-        _ => ty_id,
+    let mut cur = ty_id;
+    for _ in 0..=MAX_RESOLVE_DEPTH {
+        cur = match btf
+            .types()
+            .get(cur as usize)
+            .ok_or_else(|| anyhow!("Invalid type: {}", cur))?
+        {
+            BtfType::Typedef(BtfTypedef { type_id, .. })
+            | BtfType::Const(BtfConst { type_id })
+            | BtfType::Restrict(BtfRestrict { type_id })
+            | BtfType::Volatile(BtfVolatile { type_id })
+            | BtfType::TypeTag(BtfTypeTag { type_id, .. }) => *type_id,
+            _ => return Ok(cur),
+        };
+    }
+    bail!(
+        "Type {} is nested deeper than {} levels of typedefs and modifiers",
+        ty_id,
+        MAX_RESOLVE_DEPTH
+    )
+}
+
+/// Describe a type for error messages, e.g. ``struct `S` (type id 3)``
+pub(crate) fn describe_type(btf: &Btf, ty_id: u32) -> String {
+    let ty = btf.type_by_id(ty_id);
+    let kind = match ty {
+        BtfType::Struct(_) => "struct",
+        BtfType::Union(_) => "union",
+        BtfType::Enum(_) => "enum",
+        BtfType::Int(_) => "int",
+        BtfType::Float(_) => "float",
+        BtfType::Array(_) => "array",
+        BtfType::Ptr(_) => "pointer",
+        BtfType::Typedef(_) => "typedef",
+        _ => "type",
     };
-    Ok(result)
+    match ty.name() {
+        "" => format!("anonymous {} (type id {})", kind, ty_id),
+        s => format!("{} `{}` (type id {})", kind, s, ty_id),
+    }
+}
+/// Describe where a type is used, for error messages. Finds the first struct or union member, or array, referring to it
+pub(crate) fn describe_usage(btf: &Btf, ty_id: u32) -> Option<String> {
+    let refers = |other: u32| matches!(lookup_types(btf, other), Ok(v) if v == ty_id);
+    btf.types()
+        .iter()
+        .enumerate()
+        .find_map(|(id, ty)| match ty {
+            BtfType::Struct(comp) | BtfType::Union(comp) => comp
+                .members
+                .iter()
+                .enumerate()
+                .find(|(_, m)| refers(m.type_id))
+                .map(|(idx, m)| {
+                    format!(
+                        "field `{}` of {}",
+                        member_name(m.name, idx),
+                        describe_type(btf, id as u32)
+                    )
+                }),
+            BtfType::Array(array) if refers(array.val_type_id) => {
+                Some(format!("element of {}", describe_type(btf, id as u32)))
+            }
+            _ => None,
+        })
 }
 
-/// Currently, btfdump doesn't support load BTF from a btf archive
-/// So if we want to use btf archive, we have to wrap that into an ELF..
-pub fn create_elf_with_btf_section(btf_data: &[u8], is_64: bool) -> Result<Vec<u8>> {
-    let mut obj = ArtifactBuilder::new(if is_64 {
-        triple!("x86_64-unknown-unknown-unknown-elf")
-    } else {
-        triple!("i386-unknown-unknown-unknown-elf")
-    })
-    .name("btf-archive.bpf.o".into())
-    .finish();
-    obj.declare(".BTF", Decl::section(SectionKind::Data))
-        .map_err(|e| anyhow!("Failed to build ELF from BTF: {}", e))?;
-    obj.define(".BTF", btf_data.to_vec())
-        .map_err(|e| anyhow!("Failed to build ELF from BTF: {}", e))?;
-    obj.emit()
-        .map_err(|e| anyhow!("Failed to build ELF: {}", e))
+/// Load the BTF from the content of a file, which is either a plain btf archive, or an ELF file with the `.BTF` section.
+/// If `base` is given, the file holds a split BTF on top of it
+pub fn load_btf<'a>(
+    data: &'a [u8],
+    is_btf_archive: bool,
+    base: Option<&Btf<'a>>,
+) -> Result<Btf<'a>> {
+    match (is_btf_archive, base) {
+        (true, None) => Btf::from_bytes(data),
+        (true, Some(base)) => Btf::from_split_bytes(data, base),
+        (false, None) => Btf::from_elf_bytes(data),
+        (false, Some(base)) => {
+            let elf = ElfFile::parse(data).map_err(|e| anyhow!("Failed to parse ELF: {}", e))?;
+            Btf::load_split(&elf, base)
+        }
+    }
 }
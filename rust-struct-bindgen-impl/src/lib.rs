@@ -10,11 +10,25 @@
 //!
 //! # The generating stragety
 //!
-//! - For every `struct`, `enum`, `int`, `float`, `array`, `ptr`, `rust-struct-bindgen` will generate a represented type named `btf_type_XX`, where `XX` is the btf type id of the corresponding type for them. And the corresponding serializing and deserializing function will also be generated.  
+//! - For every `struct`, `union`, `enum`, `int`, `float`, `array`, `ptr`, `rust-struct-bindgen` will generate a represented type named `btf_type_XX`, where `XX` is the btf type id of the corresponding type for them. And the corresponding serializing and deserializing function will also be generated.  
 //! - Serializing functions always have signature like `fn (&T) -> Result<Vec<u8>, String>`where `T` is the generated rust type. The function name will always be `deserialize_btf_type_XX`, where `XX` is the type id. This function will be in the `inner_impl` module.
 //! - Deserializing functions have signature like `fn (&[u8]) -> Result<T, String>`, `T` is also the generated rust type. The function name will always be `serialize_btf_type_XX`, where `XX` is the type id. This function will be in the `inner_impl` module.
-//! - struct and enums will have a type alias pointing to the `btf_type_XX` type. The alias will be its original name. And deserializing function and serializing function will also be associated functions of the alias type.
-//! - The alias of `struct` and `enums` will be put at the top level module. All other type definitions and (de)serializing functions will be put under a module named `inner_impl`; All things are `pub`.
+//! - Encoding functions have signature like `fn (&T, &mut [u8]) -> Result<(), String>`, writing the value into a caller provided slice of exactly the size of the type without allocating. The function name will always be `encode_btf_type_XX`, and the serializing function allocates the bytes and calls it. Errors are `String`s, like the errors of the deserializing functions, rather than a dedicated error type, so that the generated code depends on nothing but `std`.
+//! - Pointers are represented by `u64` holding the address, stored in the pointer size of the BTF (4 or 8 bytes). Addresses not fitting in 4 bytes pointers fail to encode.
+//! - `union` is represented by a struct holding its raw bytes, with `get_XX`, `set_XX` and `from_XX` methods for every member `XX`, which decode the bytes as that member, write that member into the bytes and construct the union from that member.
+//! - `enum` (both `BTF_KIND_ENUM` and `BTF_KIND_ENUM64`) is represented by a rust enum, whose repr is the integer type of its size. It's signed only if the kind flag of the enum says so, which older compilers don't set.
+//!   Decoding a value not defined in the BTF fails, unless the enum is selected by `Builder::open_enum`, which adds an `Unknown(repr)` variant holding such values (`Unknown_(repr)` if the enum has a member named `Unknown`).
+//!   The enums selected by `Builder::bitflags_enum` are represented by bit flags instead: a newtype of the repr with a const for every member, holding any combination of bits. With `Builder::detect_bitflags(true)`, so are the enums with at least three members whose values are distinct powers of two.
+//!   A member sharing its value with an earlier member becomes an associated const of the variant of the earlier member.
+//!   `Display` and `FromStr` use the names of the C constants, `value` and `from_value` convert from and to the repr, and `VARIANTS` lists `(name, value)` of every member, with the value as `i64`, so that `u64` values above `i64::MAX` wrap to negative numbers. Bit flags print and parse the set flags and the remaining bits in hex, like `A | B | 0x10`.
+//! - Arrays of `char` and `signed char` are represented by the `StringPolicy` given to `Builder::string_policy`, `String` by default, while arrays of `unsigned char` (like `__u8`) are `[u8; N]`. `Builder::type_string_policy` chooses the policy by the name of the element type. Fields selected by `Builder::field_string_policy` use another binding of the same array, named like `btf_type_XX_raw`.
+//! - Bitfield members of structs and unions are decoded to the smallest fitting rust integer (or `bool`, or the enum type), and merged into the shared storage unit when serializing. Integers whose bits are not a multiple of 8 are handled in the same way.
+//! - Every struct and union also gets a view `btf_type_XX_view<'a>`, borrowing the bytes instead of decoding them. Its length is checked once by `new`, and every member is read only when its accessor (`f_XX` for structs, `get_XX` for unions) is called: nested structs and unions yield views, arrays yield `ArrayView`, and char arrays yield `&CStr` or byte slices by their string policies. Named ones have an alias like `SView`.
+//!   The mutable view `btf_type_XX_view_mut<'a>` (alias `SViewMut`) writes single members in place with setters `set_XX`, leaving all other bytes, including padding, as they were, and `set` writes the whole struct or union. Nested structs are written member by member, so their padding is kept as well. Nested structs and unions are reachable as mutable views, and arrays as `ArrayViewMut`, which writes elements one by one and yields the mutable views of struct and union elements by `get_mut`.
+//! - struct, unions and enums will have a type alias pointing to the `btf_type_XX` type. The alias will be its original name. And deserializing function, serializing function and encoding function will also be associated functions of the alias type (`from_bytes`, `to_bytes` and `encode_into`).
+//! - Typedefs, `const`, `volatile`, `restrict` and type tags are resolved to the type they finally refer to wherever a type is used. Named typedefs also get a top level alias (e.g. `pub type pid_t = inner_impl::btf_type_XX;`), unless the name is already taken by a struct, union or enum, or collides with a rust builtin type.
+//! - The alias of `struct`, `union`, `enums` and typedefs will be put at the top level module. All other type definitions and (de)serializing functions will be put under a module named `inner_impl`; All things are `pub`. Anonymous types have no alias.
+//!
 //! e.g, for a btf int with sz 64bits and unsigned encoding and typeid `1`, `rust-struct-bindgen` will generate the following code:
 //! ```rust
 //!    #[allow(unused)]
@@ -28,70 +42,214 @@
 //!        Ok(u64::from_ne_bytes(b.try_into().unwrap()))
 //!    }
 //!    #[allow(unused)]
+//!    pub fn encode_btf_type_1(v: &btf_type_1, out: &mut [u8]) -> Result<(), String> {
+//!        if out.len() != 8usize {
+//!            return Err(format!("Expected a slice with {} bytes, found {}", 8usize, out.len()));
+//!        }
+//!        out.copy_from_slice(&v.to_ne_bytes());
+//!        Ok(())
+//!    }
+//!    #[allow(unused)]
 //!    pub fn serialize_btf_type_1(v: &btf_type_1) -> Result<Vec<u8>, String> {
-//!        Ok(v.to_ne_bytes().to_vec())
+//!        let mut result = vec![0u8; 8usize];
+//!        encode_btf_type_1(v, &mut result)?;
+//!        Ok(result)
 //!    }
 //!```
+//!
+//! # Loading BTF
+//!
+//! The `btf` module loads BTF from ELF files (`Btf::load`, `Btf::from_elf_bytes`) and plain btf archives (`Btf::from_bytes`), without wrapping archives into an ELF file.
+//!
+//! This is a breaking change: `btf` used to re-export the `btfdump` crate, and is now a module of this crate. The types keep the names of `btfdump` (`btf::types::{Btf, BtfType, BtfComposite, ...}`), but their fields and methods differ, e.g. `Btf::from_bytes` is new, and enum values are `i128`. `CHANGELOG.md` explains why `btfdump` was replaced. `helper::create_elf_with_btf_section` is removed, since plain btf archives are loaded directly.
+//!
+//! # Selecting types
+//!
+//! By default every type in the BTF is generated. With `Builder::allowlist_type` and `Builder::blocklist_type`, which accept regexes of type names, only the selected named types and everything they refer to are generated. Pointees are not followed, since pointers are represented as plain integers.
+//!
+//! A split BTF (e.g. the BTF of a kernel module, loaded with `Btf::from_split_bytes` on top of vmlinux) keeps the type ids of its base. Without allowlist patterns, only the types it defines and the base types they refer to are generated.
+//!
+//! # Options
+//!
+//! `Builder` collects `BindgenOptions` and runs the generator. The CLI flags and the `key = "value"` arguments of the proc macros are mapped onto the same options, so every option is available in all of them.
+//!
+//! # Dynamic decoding
+//!
+//! If the type is only known at runtime, `dynamic::decode` interprets bytes with the BTF directly, yielding a `dynamic::Value` tree, and `dynamic::encode` turns a `dynamic::Value` back to bytes. They follow the same layout rules as the generated code.
+
+use std::collections::HashSet;
 
-use anyhow::Result;
-use btf::types::{Btf, BtfType};
+use crate::btf::types::{Btf, BtfComposite, BtfType};
+use anyhow::{anyhow, Context, Result};
 use cache::SizeResolveCache;
+use filter::TypeFilter;
+use helper::{describe_type, describe_usage, is_valid_alias_name};
 use proc_macro2::TokenStream;
 
-pub use btf;
 pub use object;
 
 use quote::quote;
 use types::{
-    array::generate_binding_for_array, enumeration::generate_binding_for_enum,
-    float::generate_binding_for_float, generate_binding_for_pointer,
-    integer::generate_binding_for_integer, structure::generate_binding_for_struct,
+    array::{generate_binding_for_array, StringPolicies},
+    bitfield::generate_bitfield_helpers,
+    enumeration::{generate_binding_for_bitflags, generate_binding_for_enum, EnumKinds},
+    float::generate_binding_for_float,
+    generate_binding_for_pointer,
+    integer::generate_binding_for_integer,
+    structure::generate_binding_for_struct,
+    typedef::generate_binding_for_typedef,
+    union::generate_binding_for_union,
+    view::{generate_view, generate_view_alias, generate_view_helpers, generate_view_mut},
 };
+/// Load BTF from ELF files or plain btf archives
+pub mod btf;
 pub(crate) mod cache;
+/// Decode and encode bytes with BTF types picked at runtime
+pub mod dynamic;
+pub(crate) mod filter;
 /// Some helper functions
 pub mod helper;
+pub(crate) mod options;
 pub(crate) mod types;
+
+pub use options::{BindgenOptions, Builder, StringPolicy};
+
 /// Generate a TokenStream for the specified Btf
 pub fn generate_bindgen_token_stream(btf: &Btf) -> Result<TokenStream> {
-    let mut inner_impl = TokenStream::new();
+    Builder::new().generate(btf)
+}
+pub(crate) fn generate_with_options(btf: &Btf, options: &BindgenOptions) -> Result<TokenStream> {
+    let filter = TypeFilter::new(options)?;
+    let enums = EnumKinds::new(options)?;
+    let strings = StringPolicies::new(options)?;
+    let selected = filter.selected_types(btf);
+    let is_selected = |ty_id: u32| selected.as_ref().map_or(true, |v| v.contains(&ty_id));
+    let mut inner_impl = generate_bitfield_helpers();
+    inner_impl.extend(generate_view_helpers());
     let mut outer_impl = TokenStream::new();
     let mut size_cache = SizeResolveCache::new(btf);
+    // Names already used by top level aliases. The first type with a name wins
+    let mut top_level_names = HashSet::new();
+    // Aliases of views are named like `SView` and `SViewMut`, which must not shadow a BTF type of that name
+    let btf_names = btf.types().iter().map(|v| v.name()).collect::<HashSet<_>>();
+    let view_aliases = |comp: &BtfComposite, ty_id: u32| {
+        let mut result = TokenStream::new();
+        for (suffix, mutable) in [("View", false), ("ViewMut", true)] {
+            if !btf_names.contains(format!("{}{}", comp.name, suffix).as_str()) {
+                result.extend(generate_view_alias(comp, ty_id, mutable));
+            }
+        }
+        result
+    };
     for (ty_id, ty) in btf.types().iter().enumerate().map(|v| (v.0 as u32, v.1)) {
+        if !is_selected(ty_id) {
+            continue;
+        }
+        let context = || match describe_usage(btf, ty_id) {
+            Some(usage) => anyhow!(
+                "Failed to generate binding for {}, used by {}",
+                describe_type(btf, ty_id),
+                usage
+            ),
+            None => anyhow!(
+                "Failed to generate binding for {}",
+                describe_type(btf, ty_id)
+            ),
+        };
         match ty {
             BtfType::Struct(comp) => {
                 let (outer, inner) =
-                    generate_binding_for_struct(btf, comp, ty_id, &mut size_cache)?;
+                    generate_binding_for_struct(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?;
                 inner_impl.extend(inner);
-                outer_impl.extend(outer);
+                inner_impl.extend(
+                    generate_view(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?,
+                );
+                inner_impl.extend(
+                    generate_view_mut(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?,
+                );
+                if !filter.is_blocked(comp.name) && top_level_names.insert(comp.name) {
+                    outer_impl.extend(outer);
+                    outer_impl.extend(view_aliases(comp, ty_id));
+                }
+            }
+            BtfType::Union(comp) => {
+                let (outer, inner) =
+                    generate_binding_for_union(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?;
+                inner_impl.extend(inner);
+                inner_impl.extend(
+                    generate_view(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?,
+                );
+                inner_impl.extend(
+                    generate_view_mut(btf, comp, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?,
+                );
+                if !filter.is_blocked(comp.name) && top_level_names.insert(comp.name) {
+                    outer_impl.extend(outer);
+                    outer_impl.extend(view_aliases(comp, ty_id));
+                }
             }
             BtfType::Int(btf_int) => {
-                inner_impl.extend(generate_binding_for_integer(
-                    btf,
-                    btf_int,
-                    ty_id,
-                    &mut size_cache,
-                )?);
+                inner_impl.extend(
+                    generate_binding_for_integer(btf, btf_int, ty_id, &mut size_cache)
+                        .with_context(context)?,
+                );
             }
             BtfType::Array(array) => {
-                inner_impl.extend(generate_binding_for_array(
-                    btf,
-                    array,
-                    ty_id,
-                    &mut size_cache,
-                )?);
+                inner_impl.extend(
+                    generate_binding_for_array(btf, array, ty_id, &mut size_cache, &strings)
+                        .with_context(context)?,
+                );
+            }
+            BtfType::Float(ft) => {
+                inner_impl.extend(generate_binding_for_float(btf, ft, ty_id).with_context(context)?)
             }
-            BtfType::Float(ft) => inner_impl.extend(generate_binding_for_float(btf, ft, ty_id)?),
             BtfType::Enum(btf_enum) => {
-                let (outer, inner) = generate_binding_for_enum(btf, btf_enum, ty_id)?;
+                let (outer, inner) = if enums.is_bitflags(btf_enum) {
+                    generate_binding_for_bitflags(btf, btf_enum, ty_id)
+                } else {
+                    generate_binding_for_enum(btf, btf_enum, ty_id, enums.is_open(btf_enum))
+                }
+                .with_context(context)?;
                 inner_impl.extend(inner);
-                outer_impl.extend(outer);
+                if !filter.is_blocked(btf_enum.name) && top_level_names.insert(btf_enum.name) {
+                    outer_impl.extend(outer);
+                }
             }
             BtfType::Ptr(_) => {
-                inner_impl.extend(generate_binding_for_pointer(btf, ty_id)?);
+                inner_impl.extend(generate_binding_for_pointer(btf, ty_id).with_context(context)?);
             }
             _ => continue,
         }
     }
+    // Typedefs are handled after all structs, unions and enums, so that `typedef struct A A` won't shadow `struct A`
+    let mut anon_impl_emitted = HashSet::new();
+    for (ty_id, ty) in btf.types().iter().enumerate().map(|v| (v.0 as u32, v.1)) {
+        if let BtfType::Typedef(typedef) = ty {
+            if !is_selected(ty_id)
+                || filter.is_blocked(typedef.name)
+                || !is_valid_alias_name(typedef.name)
+                || top_level_names.contains(typedef.name)
+            {
+                continue;
+            }
+            if let Some(outer) = generate_binding_for_typedef(btf, typedef, &mut anon_impl_emitted)
+                .with_context(|| {
+                    anyhow!(
+                        "Failed to generate binding for {}",
+                        describe_type(btf, ty_id)
+                    )
+                })?
+            {
+                top_level_names.insert(typedef.name);
+                outer_impl.extend(outer);
+            }
+        }
+    }
 
     Ok(quote! {
         #[allow(unused)]
@@ -0,0 +1,331 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use proc_macro2::TokenStream;
+
+use crate::{btf::types::Btf, generate_with_options, helper::load_btf};
+
+/// Options controlling the generated code. Use `Builder` to construct it
+#[derive(Debug, Clone, Default)]
+pub struct BindgenOptions {
+    /// Regexes of type names to generate. If empty, all types are generated
+    pub allowlist_type: Vec<String>,
+    /// Regexes of type names not to generate, unless other generated types refer to them
+    pub blocklist_type: Vec<String>,
+    /// Regexes of enum names to generate as open enums, which keep unknown values in an `Unknown` variant instead of failing to decode. `.*` makes every enum open, including anonymous ones
+    pub open_enum: Vec<String>,
+    /// Regexes of enum names to generate as bit flags
+    pub bitflags_enum: Vec<String>,
+    /// Also generate the enums with at least three members whose values are distinct powers of two as bit flags, unless selected by `open_enum`. Disabled by default, so that enums are only changed on request
+    pub detect_bitflags: bool,
+    /// How arrays of `char` and `signed char` are represented, unless overridden by `type_string_policy` or `field_string_policy`. Arrays of `unsigned char` (like `__u8`) are `[u8; N]` by default
+    pub string_policy: StringPolicy,
+    /// Policies of arrays by their element types, in the form of `PATTERN=POLICY`. The regex `PATTERN` is matched against the names of the element type through typedefs, like `__u8` and `unsigned char`. The first matching entry wins
+    pub type_string_policy: Vec<String>,
+    /// Policies of char array fields (of any 8 bits char), in the form of `PATTERN=POLICY`. The regex `PATTERN` is matched against `<struct or union name>.<field name>`, where the name of an anonymous struct or union is empty. The first matching entry wins
+    pub field_string_policy: Vec<String>,
+}
+
+/// How a char array (`char[N]`) is represented in the generated code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StringPolicy {
+    /// `String`. Decoding requires a NUL byte and valid UTF-8, and encoding leaves room for the NUL byte
+    #[default]
+    Strict,
+    /// `String`. Decoding stops at the first NUL byte or the end of the array, and replaces invalid UTF-8 with `U+FFFD`. A string filling the whole array is encoded without a NUL byte
+    Lossy,
+    /// `String`. Like `Strict`, but decoding stops at the first NUL byte or the end of the array, and a string filling the whole array is encoded without a NUL byte
+    NulOptional,
+    /// `std::ffi::CString`. Decoding stops at the first NUL byte or the end of the array, and encoding leaves room for the NUL byte
+    CString,
+    /// `Vec<u8>` holding the bytes before the first NUL byte. Encoding pads it with zeros
+    Bytes,
+    /// `[u8; N]` holding the bytes as they are
+    Raw,
+}
+
+impl StringPolicy {
+    /// All policies, in the order of their names in `NAMES`
+    pub const ALL: [StringPolicy; 6] = [
+        StringPolicy::Strict,
+        StringPolicy::Lossy,
+        StringPolicy::NulOptional,
+        StringPolicy::CString,
+        StringPolicy::Bytes,
+        StringPolicy::Raw,
+    ];
+    /// Names of the policies, accepted by `from_str`
+    pub const NAMES: [&'static str; 6] =
+        ["strict", "lossy", "nul_optional", "cstring", "bytes", "raw"];
+    /// Name of the policy, like `nul_optional`
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[Self::ALL.iter().position(|v| v == self).unwrap()]
+    }
+}
+
+impl fmt::Display for StringPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for StringPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .position(|v| *v == s)
+            .map(|idx| Self::ALL[idx])
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown string policy `{}`, expected one of: {}",
+                    s,
+                    Self::NAMES.join(", ")
+                )
+            })
+    }
+}
+
+impl BindgenOptions {
+    /// Set an option by its name, with the value in text form.
+    ///
+    /// This is how the proc macros (`key = "value"`) map onto the options, so that every option is available there. Options holding a list append the value.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "allowlist_type" => self.allowlist_type.push(value.to_string()),
+            "blocklist_type" => self.blocklist_type.push(value.to_string()),
+            "open_enum" => self.open_enum.push(value.to_string()),
+            "bitflags_enum" => self.bitflags_enum.push(value.to_string()),
+            "detect_bitflags" => {
+                self.detect_bitflags = value.parse().map_err(|_| {
+                    anyhow!(
+                        "Expected `true` or `false` for detect_bitflags, found `{}`",
+                        value
+                    )
+                })?
+            }
+            "string_policy" => self.string_policy = value.parse()?,
+            "type_string_policy" => self.type_string_policy.push(value.to_string()),
+            "field_string_policy" => self.field_string_policy.push(value.to_string()),
+            s => bail!(
+                "Unknown option `{}`, expected one of: {}",
+                s,
+                Self::KEYS.join(", ")
+            ),
+        }
+        Ok(())
+    }
+    /// Names of all options accepted by `set`
+    pub const KEYS: &'static [&'static str] = &[
+        "allowlist_type",
+        "blocklist_type",
+        "open_enum",
+        "bitflags_enum",
+        "detect_bitflags",
+        "string_policy",
+        "type_string_policy",
+        "field_string_policy",
+    ];
+}
+
+/// Configure and run the generator
+///
+/// ```ignore
+/// let tokens = Builder::new()
+///     .allowlist_type("event_.*")
+///     .blocklist_type("event_internal")
+///     .generate(&btf)?;
+/// ```
+///
+/// In build scripts, the input file can be given to the builder, and the bindings written to `OUT_DIR`:
+/// ```ignore
+/// Builder::from_elf_path("src/bpf/prog.bpf.o")
+///     .rustfmt(true)
+///     .write_to_file(Path::new(&std::env::var("OUT_DIR")?).join("bindings.rs"))?;
+/// ```
+/// Then `include!(concat!(env!("OUT_DIR"), "/bindings.rs"));` in the crate
+#[derive(Debug, Clone)]
+pub struct Builder {
+    options: BindgenOptions,
+    input: Option<Input>,
+    base_btf: Option<PathBuf>,
+    rustfmt: bool,
+    emit_rerun_if_changed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Input {
+    path: PathBuf,
+    is_btf_archive: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            options: BindgenOptions::default(),
+            input: None,
+            base_btf: None,
+            rustfmt: false,
+            emit_rerun_if_changed: true,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a builder with existing options
+    pub fn with_options(options: BindgenOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+    /// Create a builder reading the BTF from the `.BTF` section of an ELF file
+    pub fn from_elf_path(path: impl Into<PathBuf>) -> Self {
+        Self {
+            input: Some(Input {
+                path: path.into(),
+                is_btf_archive: false,
+            }),
+            ..Default::default()
+        }
+    }
+    /// Create a builder reading a plain btf archive, like `/sys/kernel/btf/vmlinux`
+    pub fn from_btf_path(path: impl Into<PathBuf>) -> Self {
+        Self {
+            input: Some(Input {
+                path: path.into(),
+                is_btf_archive: true,
+            }),
+            ..Default::default()
+        }
+    }
+    /// Treat the input file as a split BTF, like `/sys/kernel/btf/<module>`, on top of the plain btf archive at `path`, like `/sys/kernel/btf/vmlinux`.
+    /// Types of the base BTF are only generated if the split BTF refers to them, unless selected by `allowlist_type`
+    pub fn base_btf_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.base_btf = Some(path.into());
+        self
+    }
+    /// Modify the options collected so far
+    pub fn options_mut(&mut self) -> &mut BindgenOptions {
+        &mut self.options
+    }
+    /// The options collected so far
+    pub fn options(&self) -> &BindgenOptions {
+        &self.options
+    }
+    /// Only generate the types whose names match the regex, and the types they refer to. Patterns must match the whole name; can be called multiple times
+    pub fn allowlist_type(mut self, pattern: impl Into<String>) -> Self {
+        self.options.allowlist_type.push(pattern.into());
+        self
+    }
+    /// Don't generate the types whose names match the regex, unless other generated types refer to them. Patterns must match the whole name; can be called multiple times
+    pub fn blocklist_type(mut self, pattern: impl Into<String>) -> Self {
+        self.options.blocklist_type.push(pattern.into());
+        self
+    }
+    /// Generate the enums whose names match the regex as open enums, with an `Unknown` variant holding values not defined in the BTF. Patterns must match the whole name; can be called multiple times
+    pub fn open_enum(mut self, pattern: impl Into<String>) -> Self {
+        self.options.open_enum.push(pattern.into());
+        self
+    }
+    /// Generate the enums whose names match the regex as bit flags, a newtype of the integer holding any combination of the members. Patterns must match the whole name; can be called multiple times
+    pub fn bitflags_enum(mut self, pattern: impl Into<String>) -> Self {
+        self.options.bitflags_enum.push(pattern.into());
+        self
+    }
+    /// Also generate the enums with at least three members whose values are distinct powers of two as bit flags, unless selected by `open_enum`. Disabled by default
+    pub fn detect_bitflags(mut self, enable: bool) -> Self {
+        self.options.detect_bitflags = enable;
+        self
+    }
+    /// Represent arrays of `char` and `signed char` by the policy, unless overridden by `type_string_policy` or `field_string_policy`
+    pub fn string_policy(mut self, policy: StringPolicy) -> Self {
+        self.options.string_policy = policy;
+        self
+    }
+    /// Represent the arrays whose element type has a name matching the regex by the policy, like `__u8` (`[u8; N]` by default) with `StringPolicy::NulOptional` to decode them as text, or `char` with `StringPolicy::Raw` to keep them as bytes. Can be called multiple times, and the first matching pattern wins
+    pub fn type_string_policy(mut self, pattern: impl AsRef<str>, policy: StringPolicy) -> Self {
+        self.options
+            .type_string_policy
+            .push(format!("{}={}", pattern.as_ref(), policy));
+        self
+    }
+    /// Represent the char array fields (of any 8 bits char) matching the regex by the policy. The pattern must match the whole `<struct or union name>.<field name>`; can be called multiple times, and the first matching pattern wins
+    pub fn field_string_policy(mut self, pattern: impl AsRef<str>, policy: StringPolicy) -> Self {
+        self.options
+            .field_string_policy
+            .push(format!("{}={}", pattern.as_ref(), policy));
+        self
+    }
+    /// Set an option by its name. See `BindgenOptions::set`
+    pub fn option(mut self, key: &str, value: &str) -> Result<Self> {
+        self.options.set(key, value)?;
+        Ok(self)
+    }
+    /// Format the source produced by `generate_source` and `write_to_file`. Requires the installation of `rustfmt`
+    pub fn rustfmt(mut self, enable: bool) -> Self {
+        self.rustfmt = enable;
+        self
+    }
+    /// Print `cargo:rerun-if-changed` for the input file in `write_to_file`. Enabled by default, since it's intended for build scripts
+    pub fn emit_rerun_if_changed(mut self, enable: bool) -> Self {
+        self.emit_rerun_if_changed = enable;
+        self
+    }
+    /// Generate a TokenStream for the specified Btf
+    pub fn generate(&self, btf: &Btf) -> Result<TokenStream> {
+        generate_with_options(btf, &self.options)
+    }
+    /// Generate the source for the input file given by `from_elf_path` or `from_btf_path`
+    pub fn generate_source(&self) -> Result<String> {
+        let input = self
+            .input
+            .as_ref()
+            .ok_or_else(|| anyhow!("No input file is given to the builder"))?;
+        let read = |path: &Path| {
+            std::fs::read(path).with_context(|| anyhow!("Failed to read {}", path.display()))
+        };
+        let base_buf = self.base_btf.as_deref().map(read).transpose()?;
+        let base = base_buf
+            .as_deref()
+            .map(Btf::from_bytes)
+            .transpose()
+            .context("Failed to parse the base BTF")?;
+        let buf = read(&input.path)?;
+        let btf =
+            load_btf(&buf, input.is_btf_archive, base.as_ref()).context("Failed to parse BTF")?;
+        let source = self
+            .generate(&btf)
+            .with_context(|| anyhow!("Failed to generate rust code"))?
+            .to_string();
+        if self.rustfmt {
+            rustfmt_wrapper::rustfmt(source).with_context(|| anyhow!("Failed to format the code"))
+        } else {
+            Ok(source)
+        }
+    }
+    /// Generate the source for the input file, and write it to `path`, which could be `include!`d
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        if self.emit_rerun_if_changed {
+            let inputs = self.input.iter().map(|v| &v.path).chain(&self.base_btf);
+            for path in inputs {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+        let source = self.generate_source()?;
+        std::fs::write(path.as_ref(), source)
+            .with_context(|| anyhow!("Failed to write {}", path.as_ref().display()))
+    }
+}
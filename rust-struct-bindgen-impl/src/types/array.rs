@@ -3,63 +3,306 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
-use anyhow::Result;
-use btf::types::{Btf, BtfArray, BtfIntEncoding, BtfType};
+use crate::btf::types::{
+    Btf, BtfArray, BtfConst, BtfIntEncoding, BtfRestrict, BtfType, BtfTypeTag, BtfTypedef,
+    BtfVolatile,
+};
+use anyhow::{anyhow, Context, Result};
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
+use regex::Regex;
 
 use crate::{
     cache::SizeResolveCache,
-    helper::{func_names_ident, ty_name},
+    helper::{encode_func_ident, func_names_ident, lookup_types, ty_name},
+    types::generate_encode_funcs,
+    BindgenOptions, StringPolicy,
 };
 
+/// The string policies of char arrays, globally, by the element types and by fields
+#[derive(Debug)]
+pub(crate) struct StringPolicies {
+    global: StringPolicy,
+    types: Vec<(Regex, StringPolicy)>,
+    fields: Vec<(Regex, StringPolicy)>,
+}
+
+/// Parse entries of `PATTERN=POLICY`
+fn parse_policy_patterns(entries: &[String], what: &str) -> Result<Vec<(Regex, StringPolicy)>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (pattern, policy) = entry.rsplit_once('=').ok_or_else(|| {
+                anyhow!(
+                    "Expected `PATTERN=POLICY` for a {} string policy, found `{}`",
+                    what,
+                    entry
+                )
+            })?;
+            let regex = Regex::new(&format!("^(?:{})$", pattern))
+                .with_context(|| anyhow!("Invalid {} pattern `{}`", what, pattern))?;
+            Ok((regex, policy.parse()?))
+        })
+        .collect()
+}
+
+impl StringPolicies {
+    pub(crate) fn new(options: &BindgenOptions) -> Result<Self> {
+        Ok(Self {
+            global: options.string_policy,
+            types: parse_policy_patterns(&options.type_string_policy, "type")?,
+            fields: parse_policy_patterns(&options.field_string_policy, "field")?,
+        })
+    }
+    /// The policy of the array, if its elements are 8 bits chars. Arrays of `char` and `signed char` are text following the global policy, and arrays of `unsigned char` (like `__u8`) are bytes, unless a name of the element type matches a type policy
+    pub(crate) fn array_policy(&self, btf: &Btf, array: &BtfArray) -> Option<StringPolicy> {
+        let elem_ty = lookup_types(btf, array.val_type_id).ok()?;
+        let default = if is_char(btf, elem_ty) {
+            self.global
+        } else if is_byte(btf, elem_ty) {
+            StringPolicy::Raw
+        } else {
+            return None;
+        };
+        let names = elem_type_names(btf, array.val_type_id);
+        Some(
+            self.types
+                .iter()
+                .find(|(regex, _)| names.iter().any(|name| regex.is_match(name)))
+                .map_or(default, |(_, policy)| *policy),
+        )
+    }
+    /// The policy of a field, if any
+    fn field_policy(&self, comp_name: &str, field_name: &str) -> Option<StringPolicy> {
+        let name = format!("{}.{}", comp_name, field_name);
+        self.fields
+            .iter()
+            .find(|(regex, _)| regex.is_match(&name))
+            .map(|(_, policy)| *policy)
+    }
+    /// Policies used by fields besides the policy of the array, each of which needs its own binding of the array
+    fn overrides(&self, array_policy: StringPolicy) -> Vec<StringPolicy> {
+        StringPolicy::ALL
+            .into_iter()
+            .filter(|policy| {
+                *policy != array_policy && self.fields.iter().any(|(_, v)| v == policy)
+            })
+            .collect()
+    }
+    /// The policy of a struct or union member, if it's an array of 8 bits chars
+    pub(crate) fn member_policy(
+        &self,
+        btf: &Btf,
+        comp_name: &str,
+        field_name: &str,
+        ty_id: u32,
+    ) -> Option<StringPolicy> {
+        match btf.type_by_id(ty_id) {
+            BtfType::Array(array) => self
+                .array_policy(btf, array)
+                .map(|policy| self.field_policy(comp_name, field_name).unwrap_or(policy)),
+            _ => None,
+        }
+    }
+    /// The type of a struct or union member, which is a binding of the char array with the policy of the field if it's overridden.
+    /// Returns the name of the type, and the names of the deserializing and serializing functions
+    pub(crate) fn member_type(
+        &self,
+        btf: &Btf,
+        comp_name: &str,
+        field_name: &str,
+        ty_id: u32,
+    ) -> (Ident, (Ident, Ident)) {
+        let policy = match btf.type_by_id(ty_id) {
+            BtfType::Array(array) => self.array_policy(btf, array).and_then(|array_policy| {
+                self.field_policy(comp_name, field_name)
+                    .filter(|policy| *policy != array_policy)
+            }),
+            _ => None,
+        };
+        match policy {
+            Some(policy) => char_array_names(ty_id, policy),
+            None => (
+                Ident::new(&ty_name(ty_id), Span::call_site()),
+                func_names_ident(ty_id),
+            ),
+        }
+    }
+}
+
+/// Names of the element type of an array through typedefs and modifiers, like `["__u8", "unsigned char"]`
+fn elem_type_names<'a>(btf: &Btf<'a>, mut ty_id: u32) -> Vec<&'a str> {
+    let mut names = vec![];
+    loop {
+        let ty = btf.type_by_id(ty_id);
+        if !ty.name().is_empty() {
+            names.push(ty.name());
+        }
+        ty_id = match ty {
+            BtfType::Typedef(BtfTypedef { type_id, .. })
+            | BtfType::Const(BtfConst { type_id })
+            | BtfType::Volatile(BtfVolatile { type_id })
+            | BtfType::Restrict(BtfRestrict { type_id })
+            | BtfType::TypeTag(BtfTypeTag { type_id, .. }) => *type_id,
+            _ => return names,
+        };
+    }
+}
+
+/// Names of the binding of a char array with a policy used by some fields, like `btf_type_12_raw`
+fn char_array_names(ty_id: u32, policy: StringPolicy) -> (Ident, (Ident, Ident)) {
+    let name = format!("{}_{}", ty_name(ty_id), policy);
+    (
+        Ident::new(&name, Span::call_site()),
+        (
+            Ident::new(&format!("deserialize_{}", name), Span::call_site()),
+            Ident::new(&format!("serialize_{}", name), Span::call_site()),
+        ),
+    )
+}
+
+/// Generate the binding of a char array with the policy
+fn generate_char_array(
+    ty_name_ident: &Ident,
+    (de_func, ser_func): &(Ident, Ident),
+    nelems: usize,
+    policy: StringPolicy,
+) -> TokenStream {
+    let elem_count_lit = Literal::usize_suffixed(nelems);
+    // A zero-length array has no room for the NUL byte
+    let policy = match policy {
+        StringPolicy::Strict if nelems == 0 => StringPolicy::NulOptional,
+        s => s,
+    };
+    // The bytes before the first NUL byte, or all bytes if there is no NUL byte
+    let trimmed = quote! {
+        let idx = b.iter().position(|v| *v == 0).unwrap_or(b.len());
+        let b = &b[..idx];
+    };
+    // Write `bytes` padded with zeros to the length of the array
+    let padded = quote! {
+        if bytes.len() > #elem_count_lit {
+            return Err(format!("String is too long! only {} bytes is allowed", #elem_count_lit));
+        }
+        out[..bytes.len()].copy_from_slice(bytes);
+        out[bytes.len()..].fill(0);
+    };
+    let (rust_ty, de_impl, enc_impl) = match policy {
+        StringPolicy::Strict => {
+            let max_len_lit = Literal::usize_suffixed(nelems - 1);
+            (
+                quote! { String },
+                quote! {
+                    let idx = b.iter().position(|v| *v == 0).ok_or_else(|| "zero byte not found when deserializing".to_string())?;
+                    String::from_utf8(b[..idx].to_vec()).map_err(|e|format!("Invalid utf8 strings when deserializling: {}",e))
+                },
+                quote! {
+                    let bytes = v.as_bytes();
+                    if bytes.len() > #max_len_lit {
+                        return Err(format!("String is too long! only {} bytes is allowed", #max_len_lit));
+                    }
+                    #padded
+                },
+            )
+        }
+        StringPolicy::Lossy => (
+            quote! { String },
+            quote! {
+                #trimmed
+                Ok(String::from_utf8_lossy(b).into_owned())
+            },
+            quote! { let bytes = v.as_bytes(); #padded },
+        ),
+        StringPolicy::NulOptional => (
+            quote! { String },
+            quote! {
+                #trimmed
+                String::from_utf8(b.to_vec()).map_err(|e|format!("Invalid utf8 strings when deserializling: {}",e))
+            },
+            quote! { let bytes = v.as_bytes(); #padded },
+        ),
+        StringPolicy::CString => (
+            quote! { std::ffi::CString },
+            quote! {
+                #trimmed
+                Ok(std::ffi::CString::new(b).unwrap())
+            },
+            quote! { let bytes = v.as_bytes_with_nul(); #padded },
+        ),
+        StringPolicy::Bytes => (
+            quote! { Vec<u8> },
+            quote! {
+                #trimmed
+                Ok(b.to_vec())
+            },
+            quote! { let bytes = &v[..]; #padded },
+        ),
+        StringPolicy::Raw => (
+            quote! { [u8; #elem_count_lit] },
+            quote! {
+                Ok(b.try_into().unwrap())
+            },
+            quote! { out.copy_from_slice(v); },
+        ),
+    };
+    let encode_funcs = generate_encode_funcs(ty_name_ident, ser_func, nelems, enc_impl);
+    quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub type #ty_name_ident = #rust_ty;
+        #[allow(unused)]
+        pub fn #de_func(b: &[u8]) -> Result<#ty_name_ident, String> {
+            if b.len() != #elem_count_lit {
+                return Err(format!("Expected a slice with {} bytes", #elem_count_lit));
+            }
+            #de_impl
+        }
+        #encode_funcs
+    }
+}
+
+/// Generate the array. Arrays of 8 bits chars are represented by their string policy, and also by the policies overriding it for some fields
 pub(crate) fn generate_binding_for_array(
     btf: &Btf,
     array: &BtfArray,
     ty_id: u32,
     size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
 ) -> Result<TokenStream> {
     let ty_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
     let (de_func, ser_func) = func_names_ident(ty_id);
     let elem_count_lit = Literal::usize_suffixed(array.nelems as usize);
-    if is_char_array(btf, array) {
-        // For char arrays, treat them as strings
-        Ok(quote! {
-            #[allow(unused)]
-            #[allow(non_camel_case_types)]
-            pub type #ty_name_ident = String;
-            #[allow(unused)]
-            pub fn #de_func(b: &[u8]) -> Result<#ty_name_ident, String> {
-                let mut idx = 0;
-                while idx < b.len() && b[idx] != 0{
-                    idx += 1;
-                }
-                if idx == b.len(){
-                    return Err("zero byte not found when deserializing".to_string());
-                }
-                String::from_utf8(b[..idx].to_vec()).map_err(|e|format!("Invalid utf8 strings when deserializling: {}",e))
-            }
-            #[allow(unused)]
-            pub fn #ser_func(v: & #ty_name_ident) -> Result<Vec<u8>,String> {
-                let mut bytes = v.as_bytes().to_vec();
-                bytes.push(0);
-                if bytes.len() > #elem_count_lit {
-                    return Err(format!("String is too long! only {} bytes is allowed", #elem_count_lit - 1));
-                }
-                while bytes.len() < #elem_count_lit {
-                    bytes.push(0);
-                }
-                Ok(bytes)
-            }
-        })
+    let elem_ty = lookup_types(btf, array.val_type_id)?;
+    if let Some(array_policy) = strings.array_policy(btf, array) {
+        let nelems = array.nelems as usize;
+        let mut result =
+            generate_char_array(&ty_name_ident, &(de_func, ser_func), nelems, array_policy);
+        for policy in strings.overrides(array_policy) {
+            let (name, funcs) = char_array_names(ty_id, policy);
+            result.extend(generate_char_array(&name, &funcs, nelems, policy));
+        }
+        Ok(result)
     } else {
-        let elem_ty_ident = Ident::new(&ty_name(array.val_type_id), Span::call_site());
+        let elem_ty_ident = Ident::new(&ty_name(elem_ty), Span::call_site());
 
-        let elem_size_lit = Literal::usize_suffixed(size_resolver.resolve(array.val_type_id));
+        let elem_size = size_resolver.resolve(elem_ty);
+        let elem_size_lit = Literal::usize_suffixed(elem_size);
+        let array_size_lit = Literal::usize_suffixed(elem_size * array.nelems as usize);
         let array_decl = quote! {
             [#elem_ty_ident; #elem_count_lit]
         };
-        let (el_de_func, el_ser_func) = func_names_ident(array.val_type_id);
+        let (el_de_func, el_ser_func) = func_names_ident(elem_ty);
+        let el_enc_func = encode_func_ident(&el_ser_func);
+        let encode_funcs = generate_encode_funcs(
+            &ty_name_ident,
+            &ser_func,
+            elem_size * array.nelems as usize,
+            quote! {
+                for i in 0..#elem_count_lit {
+                    #el_enc_func ( &v[i], &mut out[i * #elem_size_lit .. (i + 1) * #elem_size_lit] )?;
+                }
+            },
+        );
         Ok(quote! {
             #[allow(unused)]
             #[allow(non_camel_case_types)]
@@ -67,33 +310,38 @@ pub(crate) fn generate_binding_for_array(
             #[allow(unused)]
             #[allow(clippy::needless_range_loop)]
             pub fn #de_func (b: &[u8])-> Result<#ty_name_ident, String> {
+                if b.len() != #array_size_lit {
+                    return Err(format!("Expected a slice with {} bytes", #array_size_lit));
+                }
                 let mut result = vec![];
                 for i in 0..#elem_count_lit {
                     result.push(#el_de_func ( &b[i* #elem_size_lit .. (i+1) * #elem_size_lit])?);
                 }
                 Ok(result.try_into().unwrap())
             }
-            #[allow(unused)]
-            #[allow(clippy::needless_range_loop)]
-            pub fn #ser_func (v: & #ty_name_ident) -> Result<Vec<u8>, String> {
-                let mut result = vec![];
-                for i in 0..#elem_count_lit {
-                    result.extend(#el_ser_func ( &v[i] )? .into_iter());
-                }
-                Ok(result)
-            }
+            #encode_funcs
         })
     }
 }
 
-fn is_char_array(btf: &Btf, array: &BtfArray) -> bool {
-    is_char(btf, array.val_type_id)
+/// If the type is `char` or `signed char`, whose arrays are text
+pub(crate) fn is_char(btf: &Btf, ty_id: u32) -> bool {
+    match btf.type_by_id(ty_id) {
+        BtfType::Int(btf_int) => {
+            (btf_int.name.ends_with("char") || matches!(btf_int.encoding, BtfIntEncoding::Char))
+                && !btf_int.name.starts_with("unsigned")
+                && btf_int.bits == 8
+        }
+        _ => false,
+    }
 }
 
-fn is_char(btf: &Btf, ty_id: u32) -> bool {
+/// If the type is `unsigned char`, whose arrays are bytes
+pub(crate) fn is_byte(btf: &Btf, ty_id: u32) -> bool {
     match btf.type_by_id(ty_id) {
         BtfType::Int(btf_int) => {
-            (btf_int.name.ends_with("char") || matches!(btf_int.encoding, BtfIntEncoding::Char))
+            btf_int.name.ends_with("char")
+                && btf_int.name.starts_with("unsigned")
                 && btf_int.bits == 8
         }
         _ => false,
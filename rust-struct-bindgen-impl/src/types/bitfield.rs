@@ -0,0 +1,243 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use crate::btf::types::{Btf, BtfIntEncoding, BtfMember, BtfType};
+use anyhow::{bail, Result};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+use crate::helper::{encode_func_ident, func_names_ident, lookup_types, ty_name};
+
+/// What a bitfield decodes to
+pub(crate) enum BitfieldKind {
+    Bool,
+    Int {
+        signed: bool,
+    },
+    /// An enum bitfield, decoded by the deserializing function of the enum.
+    /// The bits of a signed enum are sign-extended first, so that negative enumerators fit in the bitfield
+    Enum {
+        type_id: u32,
+        size: usize,
+        signed: bool,
+    },
+}
+
+/// A bitfield member of a struct or union
+pub(crate) struct Bitfield {
+    /// Offset in bits, from the start of the struct or union
+    pub(crate) bit_offset: u32,
+    pub(crate) bit_size: u32,
+    pub(crate) kind: BitfieldKind,
+}
+
+/// Check whether the member is a bitfield. Both the kflag encoding (bit size stored in the member)
+/// and the legacy encoding (bit size and offset stored in the int type) are recognized
+pub(crate) fn resolve_bitfield(btf: &Btf, member: &BtfMember) -> Result<Option<Bitfield>> {
+    let real_ty = lookup_types(btf, member.type_id)?;
+    let type_bits = btf.get_size_of(real_ty) * 8;
+    let (bit_offset, bit_size) = match btf.type_by_id(real_ty) {
+        BtfType::Int(btf_int) if member.bit_size == 0 => (
+            member.bit_offset + btf_int.offset,
+            if btf_int.offset != 0 || btf_int.bits != type_bits {
+                btf_int.bits
+            } else {
+                type_bits
+            },
+        ),
+        _ if member.bit_size == 0 => (member.bit_offset, type_bits),
+        _ => (member.bit_offset, member.bit_size as u32),
+    };
+    if bit_size == type_bits && bit_offset % 8 == 0 {
+        return Ok(None);
+    }
+    if bit_size == 0 || bit_size > 64 {
+        bail!("Unsupported bitfield size: {}", bit_size);
+    }
+    let kind = match btf.type_by_id(real_ty) {
+        BtfType::Int(btf_int) => match btf_int.encoding {
+            BtfIntEncoding::Bool => BitfieldKind::Bool,
+            BtfIntEncoding::Signed => BitfieldKind::Int { signed: true },
+            _ => BitfieldKind::Int { signed: false },
+        },
+        BtfType::Enum(btf_enum) => BitfieldKind::Enum {
+            type_id: real_ty,
+            size: btf_enum.sz as usize,
+            signed: btf_enum.is_signed,
+        },
+        s => bail!("Unsupported bitfield type: {}", s),
+    };
+    Ok(Some(Bitfield {
+        bit_offset,
+        bit_size,
+        kind,
+    }))
+}
+
+/// The smallest rust integer type which is able to hold `bits` bits
+pub(crate) fn fitting_int_ident(bits: u32, signed: bool) -> Result<Ident> {
+    let name = match (bits, signed) {
+        (1..=8, true) => "i8",
+        (1..=8, false) => "u8",
+        (9..=16, true) => "i16",
+        (9..=16, false) => "u16",
+        (17..=32, true) => "i32",
+        (17..=32, false) => "u32",
+        (33..=64, true) => "i64",
+        (33..=64, false) => "u64",
+        (65..=128, true) => "i128",
+        (65..=128, false) => "u128",
+        (b, _) => bail!("Unsupported integer bits {}", b),
+    };
+    Ok(Ident::new(name, Span::call_site()))
+}
+
+/// Generate the expression converting the raw `u128` bits into a value of the integer type
+pub(crate) fn int_from_raw(raw: TokenStream, bits: u32, signed: bool) -> Result<TokenStream> {
+    let ty = fitting_int_ident(bits, signed)?;
+    Ok(if signed {
+        let shift = Literal::u32_unsuffixed(128 - bits);
+        quote! {
+            ((((#raw) << #shift) as i128) >> #shift) as #ty
+        }
+    } else {
+        quote! {
+            (#raw) as #ty
+        }
+    })
+}
+
+impl Bitfield {
+    /// The rust type of the decoded field
+    pub(crate) fn field_type(&self) -> Result<TokenStream> {
+        Ok(match self.kind {
+            BitfieldKind::Bool => quote! { bool },
+            BitfieldKind::Int { signed } => {
+                let ty = fitting_int_ident(self.bit_size, signed)?;
+                quote! { #ty }
+            }
+            BitfieldKind::Enum { type_id, .. } => {
+                let ty = Ident::new(&ty_name(type_id), Span::call_site());
+                quote! { #ty }
+            }
+        })
+    }
+    /// Generate the expression reading the bitfield from the byte slice `buf` of the whole struct or union.
+    /// The expression may return early with an error
+    pub(crate) fn read(&self, buf: TokenStream) -> Result<TokenStream> {
+        let bit_offset = Literal::usize_suffixed(self.bit_offset as usize);
+        let bit_size = Literal::usize_suffixed(self.bit_size as usize);
+        let raw = quote! { read_bitfield(#buf, #bit_offset, #bit_size) };
+        self.decode_raw(raw)
+    }
+    /// Generate the statements merging `value` (a reference of the field type) into the byte slice `buf` of the whole struct or union.
+    /// The statements may return early with an error
+    pub(crate) fn write(&self, buf: TokenStream, value: TokenStream) -> Result<TokenStream> {
+        let bit_offset = Literal::usize_suffixed(self.bit_offset as usize);
+        let bit_size = Literal::usize_suffixed(self.bit_size as usize);
+        let mask = Literal::u128_unsuffixed((1u128 << self.bit_size) - 1);
+        let to_raw = match self.kind {
+            BitfieldKind::Bool => quote! {
+                let raw = (*v) as u128;
+            },
+            BitfieldKind::Int { .. } => {
+                let decoded = self.decode_raw(quote! { raw })?;
+                quote! {
+                    let raw = (*v as u128) & #mask;
+                    if #decoded != *v {
+                        return Err(format!("Value {} doesn't fit in a bitfield with {} bits", v, #bit_size));
+                    }
+                }
+            }
+            BitfieldKind::Enum { type_id, size, .. } => {
+                let (_, ser_func) = func_names_ident(type_id);
+                let enc_func = encode_func_ident(&ser_func);
+                let repr = fitting_int_ident(size as u32 * 8, false)?;
+                let size = Literal::usize_suffixed(size);
+                quote! {
+                    let mut buf = [0u8; #size];
+                    #enc_func(v, &mut buf)?;
+                    let raw = (#repr::from_ne_bytes(buf) as u128) & #mask;
+                }
+            }
+        };
+        Ok(quote! {
+            {
+                let v = #value;
+                #to_raw
+                write_bitfield(#buf, #bit_offset, #bit_size, raw);
+            }
+        })
+    }
+    fn decode_raw(&self, raw: TokenStream) -> Result<TokenStream> {
+        Ok(match self.kind {
+            BitfieldKind::Bool => quote! { ((#raw) != 0) },
+            BitfieldKind::Int { signed } => int_from_raw(raw, self.bit_size, signed)?,
+            BitfieldKind::Enum {
+                type_id,
+                size,
+                signed,
+            } => {
+                let (de_func, _) = func_names_ident(type_id);
+                let repr = fitting_int_ident(size as u32 * 8, false)?;
+                let raw = if signed {
+                    let shift = Literal::u32_unsuffixed(128 - self.bit_size);
+                    quote! { (((#raw) << #shift) as i128) >> #shift }
+                } else {
+                    raw
+                };
+                quote! {
+                    #de_func(&((#raw) as #repr).to_ne_bytes())?
+                }
+            }
+        })
+    }
+}
+
+/// Generate the helper functions used to read and write bitfields.
+/// Bit offsets are counted from the least significant bit on little endian machines, and from the most significant bit on big endian machines, as C compilers do
+pub(crate) fn generate_bitfield_helpers() -> TokenStream {
+    quote! {
+        #[allow(unused)]
+        pub fn read_bitfield(b: &[u8], bit_offset: usize, bit_size: usize) -> u128 {
+            let start = bit_offset / 8;
+            let end = (bit_offset + bit_size).div_ceil(8);
+            let mut val = 0u128;
+            let shift = if cfg!(target_endian = "little") {
+                for (i, v) in b[start..end].iter().enumerate() {
+                    val |= (*v as u128) << (i * 8);
+                }
+                bit_offset % 8
+            } else {
+                for v in b[start..end].iter() {
+                    val = (val << 8) | (*v as u128);
+                }
+                (end - start) * 8 - bit_offset % 8 - bit_size
+            };
+            (val >> shift) & ((1u128 << bit_size) - 1)
+        }
+        #[allow(unused)]
+        pub fn write_bitfield(b: &mut [u8], bit_offset: usize, bit_size: usize, v: u128) {
+            let start = bit_offset / 8;
+            let end = (bit_offset + bit_size).div_ceil(8);
+            let shift = if cfg!(target_endian = "little") {
+                bit_offset % 8
+            } else {
+                (end - start) * 8 - bit_offset % 8 - bit_size
+            };
+            let mask = ((1u128 << bit_size) - 1) << shift;
+            let v = (v << shift) & mask;
+            for i in start..end {
+                let byte_shift = if cfg!(target_endian = "little") {
+                    (i - start) * 8
+                } else {
+                    (end - i - 1) * 8
+                };
+                let byte_mask = (mask >> byte_shift) as u8;
+                b[i] = (b[i] & !byte_mask) | ((v >> byte_shift) as u8 & byte_mask);
+            }
+        }
+    }
+}
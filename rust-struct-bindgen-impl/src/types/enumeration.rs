@@ -3,68 +3,279 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
-use btf::types::{Btf, BtfEnum};
+use crate::btf::types::{Btf, BtfEnum, BtfEnumValue};
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
 
-use crate::helper::{func_names_ident, ty_name};
+use crate::filter::NamePatterns;
+use crate::helper::{encode_func_ident, func_names_ident, ty_name};
+use crate::types::generate_encode_funcs;
+use crate::BindgenOptions;
 use anyhow::{bail, Result};
+
+/// How enums are represented: closed enums, open enums selected by `open_enum`, or bit flags selected by `bitflags_enum` or, with `detect_bitflags`, detected by `looks_like_bitflags`
+#[derive(Debug)]
+pub(crate) struct EnumKinds {
+    open: NamePatterns,
+    bitflags: NamePatterns,
+    detect_bitflags: bool,
+}
+
+impl EnumKinds {
+    pub(crate) fn new(options: &BindgenOptions) -> Result<Self> {
+        Ok(Self {
+            open: NamePatterns::new(&options.open_enum)?,
+            bitflags: NamePatterns::new(&options.bitflags_enum)?,
+            detect_bitflags: options.detect_bitflags,
+        })
+    }
+    /// If the enum is represented by bit flags. Enums selected by name come before the detected bit flags
+    pub(crate) fn is_bitflags(&self, btf_enum: &BtfEnum) -> bool {
+        self.bitflags.is_match(btf_enum.name)
+            || (self.detect_bitflags && !self.is_open(btf_enum) && looks_like_bitflags(btf_enum))
+    }
+    /// If the enum is open, i.e. holds values not defined in the BTF
+    pub(crate) fn is_open(&self, btf_enum: &BtfEnum) -> bool {
+        self.open.is_match(btf_enum.name)
+    }
+}
+/// The rust integer type representing the enum, by its size and signedness
+pub(crate) fn enum_repr_ident(btf_enum: &BtfEnum) -> Result<Ident> {
+    let repr = match (btf_enum.sz, btf_enum.is_signed) {
+        (1, true) => "i8",
+        (1, false) => "u8",
+        (2, true) => "i16",
+        (2, false) => "u16",
+        (4, true) => "i32",
+        (4, false) => "u32",
+        (8, true) => "i64",
+        (8, false) => "u64",
+        (s, _) => bail!("Unsupported enum size `{}` in enum `{}`", s, btf_enum.name),
+    };
+    Ok(Ident::new(repr, Span::call_site()))
+}
+
+/// A literal of the enum value, typed as the repr of the enum
+pub(crate) fn enum_value_literal(btf_enum: &BtfEnum, value: i128) -> Literal {
+    match (btf_enum.sz, btf_enum.is_signed) {
+        (1, true) => Literal::i8_suffixed(value as i8),
+        (1, false) => Literal::u8_suffixed(value as u8),
+        (2, true) => Literal::i16_suffixed(value as i16),
+        (2, false) => Literal::u16_suffixed(value as u16),
+        (4, true) => Literal::i32_suffixed(value as i32),
+        (4, false) => Literal::u32_suffixed(value as u32),
+        (_, true) => Literal::i64_suffixed(value as i64),
+        (_, false) => Literal::u64_suffixed(value as u64),
+    }
+}
+
+/// If the enum looks like bit flags: it has at least three members, and the values are distinct powers of two.
+/// Two members like `{ A = 1, B = 2 }` are more likely to be plain enumerators
+pub(crate) fn looks_like_bitflags(btf_enum: &BtfEnum) -> bool {
+    let (canonical, aliases) = split_enum_aliases(btf_enum);
+    canonical.len() >= 3
+        && aliases.is_empty()
+        && canonical
+            .iter()
+            .all(|v| v.value > 0 && (v.value & (v.value - 1)) == 0)
+}
+
+/// The top level alias of a named enum, with `from_bytes` and `to_bytes`
+fn generate_top_level_alias(btf_enum: &BtfEnum, ty_id: u32) -> TokenStream {
+    if btf_enum.name.is_empty() {
+        return TokenStream::new();
+    }
+    let ty_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (de_func, ser_func) = func_names_ident(ty_id);
+    let enc_func = encode_func_ident(&ser_func);
+    let enum_name_ident = Ident::new(btf_enum.name, Span::call_site());
+    quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub type #enum_name_ident = inner_impl:: #ty_name_ident;
+        impl #enum_name_ident {
+            #[allow(unused)]
+            pub fn from_bytes(b: &[u8]) -> Result <Self, String> {
+               inner_impl:: #de_func(b)
+            }
+            #[allow(unused)]
+            pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+                inner_impl:: #ser_func(self)
+            }
+            #[allow(unused)]
+            pub fn encode_into(&self, out: &mut [u8]) -> Result<(), String> {
+                inner_impl:: #enc_func(self, out)
+            }
+        }
+    }
+}
+
+/// Split the members into the canonical ones, which are the first member of each value, and the aliases, paired with the canonical member of the same value
+pub(crate) fn split_enum_aliases<'a, 'b>(
+    btf_enum: &'b BtfEnum<'a>,
+) -> (Vec<&'b BtfEnumValue<'a>>, Vec<(&'a str, &'a str)>) {
+    let mut canonical: Vec<&BtfEnumValue> = vec![];
+    let mut aliases = vec![];
+    for mem in btf_enum.values.iter() {
+        match canonical.iter().find(|v| v.value == mem.value) {
+            Some(v) => aliases.push((mem.name, v.name)),
+            None => canonical.push(mem),
+        }
+    }
+    (canonical, aliases)
+}
+
+/// Name of the variant holding the unknown values of an open enum. It's `Unknown`, with `_` appended while a member of the enum has the same name
+fn unknown_variant_ident(btf_enum: &BtfEnum) -> Ident {
+    let mut name = String::from("Unknown");
+    while btf_enum.values.iter().any(|v| v.name == name) {
+        name.push('_');
+    }
+    Ident::new(&name, Span::call_site())
+}
+
+/// Generate the enum. An open enum has an extra `Unknown` variant holding the values not defined in the BTF, so that decoding never fails and unknown values are kept byte-exactly.
+/// The variant is renamed to `Unknown_` if the enum has a member named `Unknown`.
+///
+/// Members sharing a value with an earlier member (like `FOO_MAX = FOO_LAST`) become associated consts of the canonical variant, so a value always decodes to the same variant.
+///
+/// `Display` and `FromStr` use the names of the C constants, and `VARIANTS` lists the names and values of all members, in the order of the BTF.
+/// The values in `VARIANTS` are `i64`, so `u64` values above `i64::MAX` wrap to negative numbers. `value` and `from_value` are typed by the repr and keep them exact
 pub(crate) fn generate_binding_for_enum(
     _btf: &Btf,
     btf_enum: &BtfEnum,
     ty_id: u32,
+    open: bool,
 ) -> Result<(TokenStream, TokenStream)> {
     let ty_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
     let (de_func, ser_func) = func_names_ident(ty_id);
-    let repr_ident = Ident::new(
-        match btf_enum.sz {
-            1 => "i8",
-            2 => "i16",
-            4 => "i32",
-            8 => "i64",
-            s => bail!("Unsupported enum size `{}` in enum `{}`", s, btf_enum.name),
+    let repr_ident = enum_repr_ident(btf_enum)?;
+    let val_size_lit = Literal::usize_suffixed(btf_enum.sz as usize);
+    let encode_funcs = generate_encode_funcs(
+        &ty_name_ident,
+        &ser_func,
+        btf_enum.sz as usize,
+        quote! {
+            out.copy_from_slice(&v.value().to_ne_bytes());
         },
-        Span::call_site(),
     );
-    let val_size_lit = Literal::usize_suffixed(btf_enum.sz as usize);
+    let (canonical, aliases) = split_enum_aliases(btf_enum);
     let (field_name_ident, field_value_lit) = {
         let mut r1 = vec![];
         let mut r2 = vec![];
-        btf_enum.values.iter().for_each(|mem| {
+        canonical.iter().for_each(|mem| {
             r1.push(Ident::new(mem.name, Span::call_site()));
-            r2.push(Literal::i64_suffixed(mem.value as i64));
+            r2.push(enum_value_literal(btf_enum, mem.value));
+        });
+        (r1, r2)
+    };
+    let (alias_name_ident, alias_target_ident) = {
+        let mut r1 = vec![];
+        let mut r2 = vec![];
+        aliases.iter().for_each(|(alias, target)| {
+            r1.push(Ident::new(alias, Span::call_site()));
+            r2.push(Ident::new(target, Span::call_site()));
         });
         (r1, r2)
     };
+    let (member_name_ident, member_name_lit, member_value_lit) = {
+        let mut r1 = vec![];
+        let mut r2 = vec![];
+        let mut r3 = vec![];
+        btf_enum.values.iter().for_each(|mem| {
+            r1.push(Ident::new(mem.name, Span::call_site()));
+            r2.push(Literal::string(mem.name));
+            r3.push(Literal::i64_suffixed(mem.value as i64));
+        });
+        (r1, r2, r3)
+    };
+    let field_name_lit = canonical
+        .iter()
+        .map(|v| Literal::string(v.name))
+        .collect::<Vec<_>>();
     let enum_name_lit = Literal::string(btf_enum.name);
-    let enum_name_ident = Ident::new(btf_enum.name, Span::call_site());
-
+    let (unknown_variant, unknown_value, unknown_from_value, unknown_display) = if open {
+        let unknown_ident = unknown_variant_ident(btf_enum);
+        (
+            quote! { #unknown_ident(#repr_ident), },
+            quote! { #ty_name_ident::#unknown_ident(s) => *s, },
+            quote! { s => Some(#ty_name_ident::#unknown_ident(s)), },
+            quote! { #ty_name_ident::#unknown_ident(s) => write!(f, "{}", s), },
+        )
+    } else {
+        (
+            TokenStream::new(),
+            TokenStream::new(),
+            quote! { _ => None, },
+            TokenStream::new(),
+        )
+    };
     Ok((
-        quote! {
-            #[allow(unused)]
-            #[allow(non_camel_case_types)]
-            pub type #enum_name_ident = inner_impl:: #ty_name_ident;
-            impl #enum_name_ident {
-                #[allow(unused)]
-                pub fn from_bytes(b: &[u8]) -> Result <Self, String> {
-                   inner_impl:: #de_func(b)
-                }
-                #[allow(unused)]
-                pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
-                    inner_impl:: #ser_func(self)
-                }
-            }
-        },
+        generate_top_level_alias(btf_enum, ty_id),
         quote! {
             #[allow(unused)]
             #[allow(non_camel_case_types)]
             #[repr(#repr_ident)]
-            #[derive(Debug, Clone)]
+            #[derive(Debug, Clone, PartialEq, Eq)]
             pub enum #ty_name_ident {
                 #(
                     #[allow(non_camel_case_types)]
                     #field_name_ident,
                 )*
+                #unknown_variant
+            }
+            #[allow(unused)]
+            impl #ty_name_ident {
+                #(
+                    #[allow(non_upper_case_globals)]
+                    pub const #alias_name_ident: Self = Self:: #alias_target_ident;
+                )*
+                pub const VARIANTS: &'static [(&'static str, i64)] = &[
+                    #(
+                        (#member_name_lit, #member_value_lit),
+                    )*
+                ];
+                pub const fn value(&self) -> #repr_ident {
+                    match self {
+                        #(
+                            #ty_name_ident :: #field_name_ident => #field_value_lit,
+                        )*
+                        #unknown_value
+                    }
+                }
+                pub const fn from_value(v: #repr_ident) -> Option<Self> {
+                    match v {
+                        #(
+                            #field_value_lit => Some(#ty_name_ident :: #field_name_ident),
+                        )*
+                        #unknown_from_value
+                    }
+                }
+            }
+            impl std::fmt::Display for #ty_name_ident {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    match self {
+                        #(
+                            #ty_name_ident :: #field_name_ident => write!(f, "{}", #field_name_lit),
+                        )*
+                        #unknown_display
+                    }
+                }
+            }
+            impl std::str::FromStr for #ty_name_ident {
+                type Err = String;
+                fn from_str(s: &str) -> Result<Self, String> {
+                    match s {
+                        #(
+                            #member_name_lit => Ok(Self:: #member_name_ident),
+                        )*
+                        _ => s
+                            .parse::<#repr_ident>()
+                            .ok()
+                            .and_then(Self::from_value)
+                            .ok_or_else(|| format!("Invalid name {} for enum {}", s, #enum_name_lit)),
+                    }
+                }
             }
 
             #[allow(unused)]
@@ -72,24 +283,176 @@ pub(crate) fn generate_binding_for_enum(
                 if b.len() != #val_size_lit {
                     return Err(format!("Expected a slice in {} bytes", #val_size_lit));
                 }
-                let val = (#repr_ident :: from_ne_bytes(b.try_into().unwrap())) as i64;
-                match val {
+                let val = #repr_ident :: from_ne_bytes(b.try_into().unwrap());
+                #ty_name_ident::from_value(val)
+                    .ok_or_else(|| format!("Invalid enum value {} for enum {}", val, #enum_name_lit))
+            }
+            #encode_funcs
+        },
+    ))
+}
+
+/// Generate the enum as bit flags: a newtype of the repr, with a const for every member. Any combination of bits decodes, and is encoded back unchanged, including bits not named by any member.
+/// `Display` lists the names of the members whose bits are set, then the remaining bits in hex, like `A | B | 0x10`, and `FromStr` parses it back. `VARIANTS`, `value` and `from_value` are the same as plain enums, except that `from_value` never fails
+pub(crate) fn generate_binding_for_bitflags(
+    _btf: &Btf,
+    btf_enum: &BtfEnum,
+    ty_id: u32,
+) -> Result<(TokenStream, TokenStream)> {
+    let ty_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (de_func, ser_func) = func_names_ident(ty_id);
+    let repr_ident = enum_repr_ident(btf_enum)?;
+    let val_size_lit = Literal::usize_suffixed(btf_enum.sz as usize);
+    let encode_funcs = generate_encode_funcs(
+        &ty_name_ident,
+        &ser_func,
+        btf_enum.sz as usize,
+        quote! {
+            out.copy_from_slice(&v.0.to_ne_bytes());
+        },
+    );
+    let (member_name_ident, member_name_lit, member_value_lit, member_i64_lit) = {
+        let mut r1 = vec![];
+        let mut r2 = vec![];
+        let mut r3 = vec![];
+        let mut r4 = vec![];
+        btf_enum.values.iter().for_each(|mem| {
+            r1.push(Ident::new(mem.name, Span::call_site()));
+            r2.push(Literal::string(mem.name));
+            r3.push(enum_value_literal(btf_enum, mem.value));
+            r4.push(Literal::i64_suffixed(mem.value as i64));
+        });
+        (r1, r2, r3, r4)
+    };
+    // Remaining bits are printed in hex, which is parsed as unsigned so that the sign bit is accepted
+    let unsigned_repr_ident = Ident::new(&format!("u{}", btf_enum.sz * 8), Span::call_site());
+    let (canonical, _) = split_enum_aliases(btf_enum);
+    let (canonical_name_ident, canonical_name_lit) = {
+        let mut r1 = vec![];
+        let mut r2 = vec![];
+        canonical.iter().for_each(|mem| {
+            r1.push(Ident::new(mem.name, Span::call_site()));
+            r2.push(Literal::string(mem.name));
+        });
+        (r1, r2)
+    };
+    let canonical_cnt_lit = Literal::usize_suffixed(canonical.len());
+    let enum_name_lit = Literal::string(btf_enum.name);
+    let display_name_lit = Literal::string(&if btf_enum.name.is_empty() {
+        ty_name(ty_id)
+    } else {
+        btf_enum.name.to_string()
+    });
+    Ok((
+        generate_top_level_alias(btf_enum, ty_id),
+        quote! {
+            #[allow(unused)]
+            #[allow(non_camel_case_types)]
+            #[repr(transparent)]
+            #[derive(Clone, Copy, PartialEq, Eq, Default)]
+            pub struct #ty_name_ident(pub #repr_ident);
+
+            #[allow(unused)]
+            impl #ty_name_ident {
+                #(
+                    #[allow(non_upper_case_globals)]
+                    pub const #member_name_ident: Self = Self(#member_value_lit);
+                )*
+                pub const VARIANTS: &'static [(&'static str, i64)] = &[
                     #(
-                        #field_value_lit => Ok(#ty_name_ident :: #field_name_ident),
+                        (#member_name_lit, #member_i64_lit),
                     )*
-                    s => {
-                        Err(format!("Invalid enum value {} for enum {}",s,#enum_name_lit))
+                ];
+                pub const fn value(&self) -> #repr_ident {
+                    self.0
+                }
+                pub const fn from_value(v: #repr_ident) -> Option<Self> {
+                    Some(Self(v))
+                }
+                pub const fn empty() -> Self {
+                    Self(0)
+                }
+                pub const fn from_bits_retain(bits: #repr_ident) -> Self {
+                    Self(bits)
+                }
+                pub const fn bits(&self) -> #repr_ident {
+                    self.0
+                }
+                pub const fn is_empty(&self) -> bool {
+                    self.0 == 0
+                }
+                pub const fn contains(&self, other: Self) -> bool {
+                    (self.0 & other.0) == other.0
+                }
+                pub fn insert(&mut self, other: Self) {
+                    self.0 |= other.0;
+                }
+                pub fn remove(&mut self, other: Self) {
+                    self.0 &= !other.0;
+                }
+            }
+            impl std::ops::BitOr for #ty_name_ident {
+                type Output = Self;
+                fn bitor(self, rhs: Self) -> Self {
+                    Self(self.0 | rhs.0)
+                }
+            }
+            impl std::ops::BitOrAssign for #ty_name_ident {
+                fn bitor_assign(&mut self, rhs: Self) {
+                    self.0 |= rhs.0;
+                }
+            }
+            impl std::fmt::Display for #ty_name_ident {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    let flags: [(&str, Self); #canonical_cnt_lit] = [#((#canonical_name_lit, Self::#canonical_name_ident)),*];
+                    let mut rest = self.0;
+                    let mut first = true;
+                    for (name, flag) in flags {
+                        if flag.0 != 0 && self.contains(flag) && (rest & flag.0) != 0 {
+                            write!(f, "{}{}", if first { "" } else { " | " }, name)?;
+                            rest &= !flag.0;
+                            first = false;
+                        }
+                    }
+                    if rest != 0 || first {
+                        write!(f, "{}{:#x}", if first { "" } else { " | " }, rest)?;
                     }
+                    Ok(())
+                }
+            }
+            impl std::fmt::Debug for #ty_name_ident {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(f, "{}({})", #display_name_lit, self)
                 }
             }
+            impl std::str::FromStr for #ty_name_ident {
+                type Err = String;
+                fn from_str(s: &str) -> Result<Self, String> {
+                    s.split('|').map(str::trim).try_fold(Self::empty(), |acc, name| {
+                        let flag = match name {
+                            #(
+                                #member_name_lit => Self:: #member_name_ident,
+                            )*
+                            _ => match name.strip_prefix("0x") {
+                                Some(hex) => #unsigned_repr_ident::from_str_radix(hex, 16)
+                                    .map(|v| Self(v as #repr_ident)),
+                                None => name.parse::<#repr_ident>().map(Self),
+                            }
+                            .map_err(|_| format!("Invalid flag {} for enum {}", name, #enum_name_lit))?,
+                        };
+                        Ok(acc | flag)
+                    })
+                }
+            }
+
             #[allow(unused)]
-            pub fn #ser_func (v: & #ty_name_ident) -> Result<Vec<u8>, String> {
-                match v {
-                    #(
-                        #ty_name_ident :: #field_name_ident => Ok((#field_value_lit as #repr_ident) . to_ne_bytes() . to_vec()),
-                    )*
+            pub fn #de_func( b:&[u8]) -> Result <#ty_name_ident, String> {
+                if b.len() != #val_size_lit {
+                    return Err(format!("Expected a slice in {} bytes", #val_size_lit));
                 }
+                Ok(#ty_name_ident(#repr_ident :: from_ne_bytes(b.try_into().unwrap())))
             }
+            #encode_funcs
         },
     ))
 }
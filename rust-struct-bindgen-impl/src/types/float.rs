@@ -3,12 +3,13 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
+use crate::btf::types::{Btf, BtfFloat};
 use anyhow::{bail, Result};
-use btf::types::{Btf, BtfFloat};
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
 
 use crate::helper::{func_names_ident, ty_name};
+use crate::types::generate_encode_funcs;
 
 pub(crate) fn generate_binding_for_float(
     _btf: &Btf,
@@ -26,6 +27,14 @@ pub(crate) fn generate_binding_for_float(
         Span::call_site(),
     );
     let size_lit = Literal::usize_suffixed(btf_float.sz as usize);
+    let encode_funcs = generate_encode_funcs(
+        &ty_name_ident,
+        &ser_func,
+        btf_float.sz as usize,
+        quote! {
+            out.copy_from_slice(&v.to_ne_bytes());
+        },
+    );
 
     Ok(quote! {
         #[allow(unused)]
@@ -40,11 +49,6 @@ pub(crate) fn generate_binding_for_float(
                 #underlying_type_ident :: from_ne_bytes(b.try_into().unwrap())
             )
         }
-        #[allow(unused)]
-        pub fn #ser_func(v: &#ty_name_ident) -> Result<Vec<u8>, String> {
-            Ok(
-                v.to_ne_bytes().to_vec()
-            )
-        }
+        #encode_funcs
     })
 }
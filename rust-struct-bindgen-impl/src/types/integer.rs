@@ -3,14 +3,18 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
+use crate::btf::types::{Btf, BtfInt, BtfIntEncoding};
 use anyhow::{bail, Result};
-use btf::types::{Btf, BtfInt, BtfIntEncoding};
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
 
 use crate::{
     cache::SizeResolveCache,
     helper::{func_names_ident, ty_name},
+    types::{
+        bitfield::{fitting_int_ident, int_from_raw},
+        generate_encode_funcs,
+    },
 };
 pub(crate) fn generate_binding_for_integer(
     _btf: &Btf,
@@ -18,8 +22,8 @@ pub(crate) fn generate_binding_for_integer(
     ty_id: u32,
     size_resolver: &mut SizeResolveCache,
 ) -> Result<TokenStream> {
-    if btf_int.bits % 8 != 0 {
-        bail!("Bitfield is not supported now");
+    if btf_int.bits % 8 != 0 || btf_int.offset != 0 {
+        return generate_binding_for_bitfield_integer(btf_int, ty_id, size_resolver);
     }
 
     let underlying_type_ident = Ident::new(
@@ -51,15 +55,21 @@ pub(crate) fn generate_binding_for_integer(
             #underlying_type_ident :: from_ne_bytes (b.try_into().unwrap())
         }
     };
-    let ser_impl = if matches!(btf_int.encoding, BtfIntEncoding::Bool) {
+    let enc_impl = if matches!(btf_int.encoding, BtfIntEncoding::Bool) {
         quote! {
-            vec![ if v {1} else {0} ]
+            out[0] = if *v {1} else {0};
         }
     } else {
         quote! {
-            v.to_ne_bytes().to_vec()
+            out.copy_from_slice(&v.to_ne_bytes());
         }
     };
+    let encode_funcs = generate_encode_funcs(
+        &type_name_ident,
+        &ser_func,
+        size_resolver.resolve(ty_id),
+        enc_impl,
+    );
     Ok(quote! {
         #[allow(unused)]
         #[allow(non_camel_case_types)]
@@ -73,12 +83,71 @@ pub(crate) fn generate_binding_for_integer(
                 #de_impl
             )
         }
+        #encode_funcs
+    })
+}
+
+/// Integers with bits not a multiple of 8, or with a bit offset, are decoded to the smallest fitting rust integer
+fn generate_binding_for_bitfield_integer(
+    btf_int: &BtfInt,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+) -> Result<TokenStream> {
+    let type_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (de_func, ser_func) = func_names_ident(ty_id);
+    let type_size_lit = Literal::usize_suffixed(size_resolver.resolve(ty_id));
+    let bit_offset_lit = Literal::usize_suffixed(btf_int.offset as usize);
+    let bits_lit = Literal::usize_suffixed(btf_int.bits as usize);
+    let (underlying_type, de_impl, to_raw) = if matches!(btf_int.encoding, BtfIntEncoding::Bool) {
+        (
+            quote! { bool },
+            quote! { read_bitfield(b, #bit_offset_lit, #bits_lit) != 0 },
+            quote! { let raw = (*v) as u128; },
+        )
+    } else {
+        let signed = matches!(btf_int.encoding, BtfIntEncoding::Signed);
+        let underlying_type_ident = fitting_int_ident(btf_int.bits, signed)?;
+        let decoded = int_from_raw(quote! { raw }, btf_int.bits, signed)?;
+        let mask = Literal::u128_unsuffixed((1u128 << btf_int.bits) - 1);
+        (
+            quote! { #underlying_type_ident },
+            int_from_raw(
+                quote! { read_bitfield(b, #bit_offset_lit, #bits_lit) },
+                btf_int.bits,
+                signed,
+            )?,
+            quote! {
+                let raw = (*v as u128) & #mask;
+                if #decoded != *v {
+                    return Err(format!("Value {} doesn't fit in an integer with {} bits", v, #bits_lit));
+                }
+            },
+        )
+    };
+    // Bits outside of the integer are padding
+    let encode_funcs = generate_encode_funcs(
+        &type_name_ident,
+        &ser_func,
+        size_resolver.resolve(ty_id),
+        quote! {
+            out.fill(0);
+            #to_raw
+            write_bitfield(out, #bit_offset_lit, #bits_lit, raw);
+        },
+    );
+    Ok(quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub type #type_name_ident = #underlying_type;
         #[allow(unused)]
-        pub fn #ser_func (v: & #type_name_ident) -> Result< Vec<u8>, String> {
+        pub fn #de_func (b: &[u8]) -> Result< #type_name_ident, String> {
+            if b.len() != #type_size_lit || b.len() * 8 < #bit_offset_lit + #bits_lit {
+                return Err(format!("Expected a slice with {} bytes", #type_size_lit))
+            }
             Ok(
-                #ser_impl
+                #de_impl
             )
         }
-
+        #encode_funcs
     })
 }
@@ -3,21 +3,84 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
-use btf::types::Btf;
+use crate::btf::types::Btf;
 
 pub(crate) mod array;
+pub(crate) mod bitfield;
 pub(crate) mod enumeration;
 pub(crate) mod float;
 pub(crate) mod integer;
 pub(crate) mod structure;
-use anyhow::Result;
-use proc_macro2::{Ident, Span, TokenStream};
+pub(crate) mod typedef;
+pub(crate) mod union;
+pub(crate) mod view;
+use anyhow::{bail, Result};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
 
-use crate::helper::{func_names_ident, ty_name};
-pub(crate) fn generate_binding_for_pointer(_btf: &Btf, ty_id: u32) -> Result<TokenStream> {
+use crate::helper::{encode_func_ident, func_names_ident, ty_name};
+
+/// Generate the encoding function, which checks the length of `out` and runs `body` to write `v` into it, and the serializing function allocating the bytes for it.
+/// `body` may return early with an error
+pub(crate) fn generate_encode_funcs(
+    ty_ident: &Ident,
+    ser_func: &Ident,
+    size: usize,
+    body: TokenStream,
+) -> TokenStream {
+    let enc_func = encode_func_ident(ser_func);
+    let size = Literal::usize_suffixed(size);
+    quote! {
+        #[allow(unused)]
+        #[allow(clippy::identity_op)]
+        #[allow(clippy::needless_range_loop)]
+        pub fn #enc_func (v: & #ty_ident, out: &mut [u8]) -> Result<(), String> {
+            if out.len() != #size {
+                return Err(format!("Expected a slice with {} bytes, found {}", #size, out.len()));
+            }
+            #body
+            Ok(())
+        }
+        #[allow(unused)]
+        pub fn #ser_func (v: & #ty_ident) -> Result<Vec<u8>, String> {
+            let mut result = vec![0u8; #size];
+            #enc_func (v, &mut result)?;
+            Ok(result)
+        }
+    }
+}
+/// The expression reading a pointer, which is always represented as `u64`, from the byte slice `b` of the pointer size of the BTF
+pub(crate) fn pointer_reader(btf: &Btf) -> Result<TokenStream> {
+    Ok(match btf.ptr_sz() {
+        4 => quote! { u32::from_ne_bytes(b[..4].try_into().unwrap()) as u64 },
+        8 => quote! { u64::from_ne_bytes(b[..8].try_into().unwrap()) },
+        s => bail!("Unsupported pointer size: {}", s),
+    })
+}
+
+/// Generate the binding of a pointer, which is a `u64` holding the address. With 4 bytes pointers, addresses not fitting in them fail to encode
+pub(crate) fn generate_binding_for_pointer(btf: &Btf, ty_id: u32) -> Result<TokenStream> {
     let ty_name_ident = Ident::new(&ty_name(ty_id), Span::call_site());
     let (de_func, ser_func) = func_names_ident(ty_id);
+    let reader = pointer_reader(btf)?;
+    let size = btf.ptr_sz() as usize;
+    let size_lit = Literal::usize_suffixed(size);
+    let encode_funcs = generate_encode_funcs(
+        &ty_name_ident,
+        &ser_func,
+        size,
+        if size == 4 {
+            quote! {
+                let v = u32::try_from(*v)
+                    .map_err(|_| format!("Pointer {:#x} doesn't fit in 4 bytes", v))?;
+                out.copy_from_slice(&v.to_ne_bytes());
+            }
+        } else {
+            quote! {
+                out.copy_from_slice(&v.to_ne_bytes());
+            }
+        },
+    );
 
     Ok(quote! {
         #[allow(unused)]
@@ -26,19 +89,13 @@ pub(crate) fn generate_binding_for_pointer(_btf: &Btf, ty_id: u32) -> Result<Tok
 
         #[allow(unused)]
         pub fn #de_func (b: &[u8]) -> Result< #ty_name_ident, String> {
-            if b.len() != 8 {
-                return Err("Expected a slice with 8 bytes".to_string());
+            if b.len() != #size_lit {
+                return Err(format!("Expected a slice with {} bytes", #size_lit));
             }
             Ok(
-                u64 :: from_ne_bytes (b.try_into().unwrap())
-
-            )
-        }
-        #[allow(unused)]
-        pub fn #ser_func (v: & #ty_name_ident) -> Result< Vec<u8>, String> {
-            Ok(
-                v.to_ne_bytes().to_vec()
+                #reader
             )
         }
+        #encode_funcs
     })
 }
@@ -3,11 +3,13 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
+use crate::btf::types::{Btf, BtfComposite};
 use crate::cache::SizeResolveCache;
-use crate::helper::{func_names_ident, lookup_types, ty_name};
+use crate::helper::{encode_func_ident, func_names_ident, lookup_types, member_name, ty_name};
+use crate::types::{array::StringPolicies, bitfield::resolve_bitfield, generate_encode_funcs};
 use anyhow::anyhow;
-use anyhow::{bail, Result};
-use btf::types::{Btf, BtfComposite};
+use anyhow::Context;
+use anyhow::Result;
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::quote;
 pub(crate) fn generate_binding_for_struct(
@@ -15,46 +17,59 @@ pub(crate) fn generate_binding_for_struct(
     comp: &BtfComposite,
     ty_id: u32,
     size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
 ) -> Result<(TokenStream, TokenStream)> {
     let st_name = Ident::new(&ty_name(ty_id), Span::call_site());
-    let alias = Ident::new(comp.name, Span::call_site());
 
-    let (
-        field_type_idents,
-        field_type_de_func_idents,
-        field_type_ser_func_idents,
-        field_names,
-        field_sizes,
-        field_offsets,
-    ) = {
+    let (field_types, field_names, field_de_exprs, field_ser_stmts) = {
         let mut r1 = vec![];
         let mut r2 = vec![];
         let mut r3 = vec![];
         let mut r4 = vec![];
-        let mut r5 = vec![];
-        let mut r6 = vec![];
-        comp.members.iter().try_for_each(|v| -> Result<()> {
-            if v.bit_size % 8 != 0 || v.bit_offset % 8 != 0 {
-                bail!("Bitfield is not supported, currently");
-            }
-            let type_name = ty_name(lookup_types(btf, v.type_id).map_err(|e| {
-                anyhow!(
-                    "Failed to lookup type for struct {} field {}: {}",
-                    comp.name,
-                    v.name,
-                    e
-                )
-            })?);
-            r1.push(Ident::new(&type_name, Span::call_site()));
-            let (i2, i3) = func_names_ident(v.type_id);
-            r2.push(i2);
-            r3.push(i3);
-            r4.push(Ident::new(&format!("f_{}", v.name), Span::call_site()));
-            r5.push(Literal::usize_suffixed(size_resolver.resolve(v.type_id)));
-            r6.push(Literal::usize_suffixed((v.bit_offset / 8) as usize));
-            Ok(())
-        })?;
-        (r1, r2, r3, r4, r5, r6)
+        comp.members
+            .iter()
+            .enumerate()
+            .try_for_each(|(idx, v)| -> Result<()> {
+                let field_name = Ident::new(
+                    &format!("f_{}", member_name(v.name, idx)),
+                    Span::call_site(),
+                );
+                let bitfield = resolve_bitfield(btf, v).with_context(|| {
+                    anyhow!(
+                        "Failed to resolve bitfield for struct `{}` field `{}`",
+                        comp.name,
+                        v.name
+                    )
+                })?;
+                if let Some(bitfield) = bitfield {
+                    r1.push(bitfield.field_type()?);
+                    r3.push(bitfield.read(quote! { b })?);
+                    r4.push(bitfield.write(quote! { out }, quote! { &v. #field_name })?);
+                } else {
+                    let real_ty = lookup_types(btf, v.type_id).with_context(|| {
+                        anyhow!(
+                            "Failed to lookup type for struct `{}` field `{}`",
+                            comp.name,
+                            v.name
+                        )
+                    })?;
+                    let (type_ident, (de_func, ser_func)) =
+                        strings.member_type(btf, comp.name, v.name, real_ty);
+                    r1.push(quote! { #type_ident });
+                    let size = Literal::usize_suffixed(size_resolver.resolve(v.type_id));
+                    let offset = Literal::usize_suffixed((v.bit_offset / 8) as usize);
+                    r3.push(quote! {
+                        #de_func ( &b[ #offset .. #offset + #size ] )?
+                    });
+                    let enc_func = encode_func_ident(&ser_func);
+                    r4.push(quote! {
+                        #enc_func ( &v. #field_name, &mut out[#offset .. #offset + #size] )?;
+                    });
+                }
+                r2.push(field_name);
+                Ok(())
+            })?;
+        (r1, r2, r3, r4)
     };
 
     let struct_decl = quote! {
@@ -63,7 +78,7 @@ pub(crate) fn generate_binding_for_struct(
         #[repr(C)]
         #[derive(Debug, Clone)]
         pub struct #st_name {
-            #(pub #field_names: #field_type_idents),*
+            #(pub #field_names: #field_types),*
         }
     };
     let (der_name, ser_name) = func_names_ident(ty_id);
@@ -77,7 +92,7 @@ pub(crate) fn generate_binding_for_struct(
                 return Err(format!("Expected a slice with length {}", #type_size))
             }
             #(
-                let #field_names = #field_type_de_func_idents ( &b[ #field_offsets .. #field_offsets + #field_sizes ] )?;
+                let #field_names = #field_de_exprs;
             )*
             Ok(
                 #st_name {
@@ -89,34 +104,41 @@ pub(crate) fn generate_binding_for_struct(
         }
 
     };
-    let serialize_func = quote! {
-        #[allow(unused)]
-        #[allow(clippy::identity_op)]
-        pub fn #ser_name (t: & #st_name) -> std::result::Result < Vec<u8> , std::string::String> {
-            let mut result = vec![0u8; #type_size ];
+    // Padding bytes are always written as zeros
+    let serialize_func = generate_encode_funcs(
+        &st_name,
+        &ser_name,
+        comp.sz as usize,
+        quote! {
+            out.fill(0);
             #(
-                {
-                    let ret = #field_type_ser_func_idents ( &t. #field_names)?;
-                    result[#field_offsets .. #field_offsets + #field_sizes].copy_from_slice ( &ret[..] );
-                }
+                #field_ser_stmts
             )*
-
-            Ok(result)
-        }
-    };
-    let outer_code = quote! {
-        #[allow(unused)]
-        #[allow(non_camel_case_types)]
-        pub type #alias = inner_impl :: #st_name;
-
-        impl #alias {
-            #[allow(unused)]
-            pub fn from_bytes(b: &[u8]) -> Result<Self, String> {
-                inner_impl:: #der_name (b)
-            }
+        },
+    );
+    let enc_name = encode_func_ident(&ser_name);
+    let outer_code = if comp.name.is_empty() {
+        TokenStream::new()
+    } else {
+        let alias = Ident::new(comp.name, Span::call_site());
+        quote! {
             #[allow(unused)]
-            pub fn to_bytes(&self) -> Result <Vec<u8>, String> {
-                inner_impl:: #ser_name (self)
+            #[allow(non_camel_case_types)]
+            pub type #alias = inner_impl :: #st_name;
+
+            impl #alias {
+                #[allow(unused)]
+                pub fn from_bytes(b: &[u8]) -> Result<Self, String> {
+                    inner_impl:: #der_name (b)
+                }
+                #[allow(unused)]
+                pub fn to_bytes(&self) -> Result <Vec<u8>, String> {
+                    inner_impl:: #ser_name (self)
+                }
+                #[allow(unused)]
+                pub fn encode_into(&self, out: &mut [u8]) -> Result<(), String> {
+                    inner_impl:: #enc_name (self, out)
+                }
             }
         }
     };
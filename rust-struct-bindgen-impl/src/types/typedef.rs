@@ -0,0 +1,61 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::collections::HashSet;
+
+use crate::btf::types::{Btf, BtfType, BtfTypedef};
+use anyhow::Result;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+use crate::helper::{encode_func_ident, func_names_ident, lookup_types, ty_name};
+
+/// Generate the top level alias for a typedef, pointing to the `btf_type_XX` of the type it finally refers to.
+///
+/// Returns `None` if no type is generated for the referred type (e.g. `void` or function prototypes).
+/// If the referred type is an anonymous struct, union or enum, which has no alias of its own, `from_bytes` and `to_bytes` will be implemented on the first typedef of it
+pub(crate) fn generate_binding_for_typedef(
+    btf: &Btf,
+    typedef: &BtfTypedef,
+    anon_impl_emitted: &mut HashSet<u32>,
+) -> Result<Option<TokenStream>> {
+    let real_ty = lookup_types(btf, typedef.type_id)?;
+    let anonymous = match btf.type_by_id(real_ty) {
+        BtfType::Struct(comp) | BtfType::Union(comp) => comp.name.is_empty(),
+        BtfType::Enum(btf_enum) => btf_enum.name.is_empty(),
+        BtfType::Int(_) | BtfType::Float(_) | BtfType::Ptr(_) | BtfType::Array(_) => false,
+        _ => return Ok(None),
+    };
+    let alias = Ident::new(typedef.name, Span::call_site());
+    let ty_name_ident = Ident::new(&ty_name(real_ty), Span::call_site());
+    let alias_impl = if anonymous && anon_impl_emitted.insert(real_ty) {
+        let (de_func, ser_func) = func_names_ident(real_ty);
+        let enc_func = encode_func_ident(&ser_func);
+        quote! {
+            impl #alias {
+                #[allow(unused)]
+                pub fn from_bytes(b: &[u8]) -> Result<Self, String> {
+                    inner_impl:: #de_func (b)
+                }
+                #[allow(unused)]
+                pub fn to_bytes(&self) -> Result <Vec<u8>, String> {
+                    inner_impl:: #ser_func (self)
+                }
+                #[allow(unused)]
+                pub fn encode_into(&self, out: &mut [u8]) -> Result<(), String> {
+                    inner_impl:: #enc_func (self, out)
+                }
+            }
+        }
+    } else {
+        TokenStream::new()
+    };
+    Ok(Some(quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub type #alias = inner_impl :: #ty_name_ident;
+        #alias_impl
+    }))
+}
@@ -0,0 +1,162 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use crate::btf::types::{Btf, BtfComposite};
+use crate::cache::SizeResolveCache;
+use crate::helper::{encode_func_ident, func_names_ident, lookup_types, member_name, ty_name};
+use crate::types::{array::StringPolicies, bitfield::resolve_bitfield, generate_encode_funcs};
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+/// Unions are represented by their raw bytes. Every member gets a getter which decodes the bytes as that member,
+/// a setter which overwrites the leading bytes with the serialized member, and a constructor.
+pub(crate) fn generate_binding_for_union(
+    btf: &Btf,
+    comp: &BtfComposite,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<(TokenStream, TokenStream)> {
+    let un_name = Ident::new(&ty_name(ty_id), Span::call_site());
+    let type_size = Literal::usize_suffixed(comp.sz as _);
+
+    let mut accessors = TokenStream::new();
+    for (idx, v) in comp.members.iter().enumerate() {
+        let name = member_name(v.name, idx);
+        let getter = Ident::new(&format!("get_{}", name), Span::call_site());
+        let setter = Ident::new(&format!("set_{}", name), Span::call_site());
+        let constructor = Ident::new(&format!("from_{}", name), Span::call_site());
+        let bitfield = resolve_bitfield(btf, v).with_context(|| {
+            anyhow!(
+                "Failed to resolve bitfield for union `{}` member `{}`",
+                comp.name,
+                v.name
+            )
+        })?;
+        let (mem_ty, get_impl, set_impl) = if let Some(bitfield) = bitfield {
+            let read_expr = bitfield.read(quote! { &self.bytes })?;
+            (
+                bitfield.field_type()?,
+                quote! {
+                    Ok(#read_expr)
+                },
+                bitfield.write(quote! { &mut self.bytes }, quote! { v })?,
+            )
+        } else {
+            let mem_ty = lookup_types(btf, v.type_id).with_context(|| {
+                anyhow!(
+                    "Failed to lookup type for union `{}` member `{}`",
+                    comp.name,
+                    v.name
+                )
+            })?;
+            let (mem_ty_ident, (de_func, ser_func)) =
+                strings.member_type(btf, comp.name, v.name, mem_ty);
+            let enc_func = encode_func_ident(&ser_func);
+            let offset = Literal::usize_suffixed((v.bit_offset / 8) as usize);
+            let size = Literal::usize_suffixed(size_resolver.resolve(v.type_id));
+            (
+                quote! { #mem_ty_ident },
+                quote! {
+                    #de_func ( &self.bytes[ #offset .. #offset + #size ] )
+                },
+                quote! {
+                    #enc_func (v, &mut self.bytes[ #offset .. #offset + #size ])?;
+                },
+            )
+        };
+        accessors.extend(quote! {
+            #[allow(unused)]
+            #[allow(clippy::identity_op)]
+            pub fn #getter (&self) -> std::result::Result< #mem_ty, std::string::String> {
+                #get_impl
+            }
+            #[allow(unused)]
+            #[allow(clippy::identity_op)]
+            pub fn #setter (&mut self, v: & #mem_ty) -> std::result::Result<(), std::string::String> {
+                #set_impl
+                Ok(())
+            }
+            #[allow(unused)]
+            pub fn #constructor (v: & #mem_ty) -> std::result::Result<Self, std::string::String> {
+                let mut result = Self { bytes: [0u8; #type_size] };
+                result. #setter (v)?;
+                Ok(result)
+            }
+        });
+    }
+
+    let union_decl = quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone)]
+        pub struct #un_name {
+            pub bytes: [u8; #type_size],
+        }
+        impl #un_name {
+            #accessors
+        }
+    };
+    let (der_name, ser_name) = func_names_ident(ty_id);
+
+    let deserialize_func = quote! {
+        #[allow(unused)]
+        pub fn #der_name (b: &[u8]) -> std::result::Result< #un_name, std::string::String> {
+            if b.len() != #type_size {
+                return Err(format!("Expected a slice with length {}", #type_size))
+            }
+            Ok(
+                #un_name {
+                    bytes: b.try_into().unwrap(),
+                }
+            )
+        }
+    };
+    let serialize_func = generate_encode_funcs(
+        &un_name,
+        &ser_name,
+        comp.sz as usize,
+        quote! {
+            out.copy_from_slice(&v.bytes);
+        },
+    );
+    let enc_name = encode_func_ident(&ser_name);
+    let outer_code = if comp.name.is_empty() {
+        TokenStream::new()
+    } else {
+        let alias = Ident::new(comp.name, Span::call_site());
+        quote! {
+            #[allow(unused)]
+            #[allow(non_camel_case_types)]
+            pub type #alias = inner_impl :: #un_name;
+
+            impl #alias {
+                #[allow(unused)]
+                pub fn from_bytes(b: &[u8]) -> Result<Self, String> {
+                    inner_impl:: #der_name (b)
+                }
+                #[allow(unused)]
+                pub fn to_bytes(&self) -> Result <Vec<u8>, String> {
+                    inner_impl:: #ser_name (self)
+                }
+                #[allow(unused)]
+                pub fn encode_into(&self, out: &mut [u8]) -> Result<(), String> {
+                    inner_impl:: #enc_name (self, out)
+                }
+            }
+        }
+    };
+    Ok((
+        outer_code,
+        quote! {
+            #union_decl
+            #deserialize_func
+            #serialize_func
+        },
+    ))
+}
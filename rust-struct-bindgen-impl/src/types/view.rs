@@ -0,0 +1,586 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use crate::btf::types::{Btf, BtfComposite, BtfIntEncoding, BtfType};
+use crate::cache::SizeResolveCache;
+use crate::helper::{encode_func_ident, func_names_ident, lookup_types, member_name, ty_name};
+use crate::types::{
+    array::StringPolicies,
+    bitfield::{int_from_raw, resolve_bitfield, BitfieldKind},
+    pointer_reader,
+};
+use crate::StringPolicy;
+use anyhow::{anyhow, bail, Context, Result};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+/// Name of the view type of a struct or union, like `btf_type_12_view`
+pub(crate) fn view_name(ty_id: u32) -> String {
+    format!("{}_view", ty_name(ty_id))
+}
+
+/// Name of the mutable view type of a struct or union, like `btf_type_12_view_mut`
+pub(crate) fn view_mut_name(ty_id: u32) -> String {
+    format!("{}_view_mut", ty_name(ty_id))
+}
+
+/// Generate `ArrayView`, which reads the elements of an array lazily, and `ArrayViewMut`, which writes them one by one.
+/// `HasViewMut` maps the owned binding of a struct or union to its mutable view, so that `ArrayViewMut::get_mut` yields the mutable views of such elements
+pub(crate) fn generate_view_helpers() -> TokenStream {
+    quote! {
+        pub trait HasViewMut<'b> {
+            type ViewMut;
+            fn view_mut(b: &'b mut [u8]) -> Self::ViewMut;
+        }
+        #[allow(unused)]
+        pub struct ArrayView<'a, T> {
+            bytes: &'a [u8],
+            elem_size: usize,
+            read: fn(&'a [u8]) -> T,
+        }
+        impl<T> Clone for ArrayView<'_, T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+        impl<T> Copy for ArrayView<'_, T> {}
+        #[allow(unused)]
+        impl<'a, T> ArrayView<'a, T> {
+            pub fn new(bytes: &'a [u8], elem_size: usize, read: fn(&'a [u8]) -> T) -> Self {
+                Self { bytes, elem_size, read }
+            }
+            pub fn len(&self) -> usize {
+                if self.elem_size == 0 {
+                    0
+                } else {
+                    self.bytes.len() / self.elem_size
+                }
+            }
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+            pub fn get(&self, idx: usize) -> Option<T> {
+                if idx < self.len() {
+                    Some((self.read)(&self.bytes[idx * self.elem_size..(idx + 1) * self.elem_size]))
+                } else {
+                    None
+                }
+            }
+            pub fn iter(&self) -> impl Iterator<Item = T> + 'a where T: 'a {
+                // The chunk size of an empty array of zero-sized elements doesn't matter, but must not be zero
+                self.bytes[..self.len() * self.elem_size]
+                    .chunks_exact(self.elem_size.max(1))
+                    .map(self.read)
+            }
+            pub fn bytes(&self) -> &'a [u8] {
+                self.bytes
+            }
+        }
+        impl<'a, T: std::fmt::Debug + 'a> std::fmt::Debug for ArrayView<'a, T> {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.debug_list().entries(self.iter()).finish()
+            }
+        }
+        #[allow(unused)]
+        pub struct ArrayViewMut<'a, T> {
+            bytes: &'a mut [u8],
+            elem_size: usize,
+            write: fn(&T, &mut [u8]) -> Result<(), String>,
+        }
+        #[allow(unused)]
+        impl<'a, T> ArrayViewMut<'a, T> {
+            pub fn new(bytes: &'a mut [u8], elem_size: usize, write: fn(&T, &mut [u8]) -> Result<(), String>) -> Self {
+                Self { bytes, elem_size, write }
+            }
+            pub fn len(&self) -> usize {
+                if self.elem_size == 0 {
+                    0
+                } else {
+                    self.bytes.len() / self.elem_size
+                }
+            }
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+            pub fn set(&mut self, idx: usize, v: &T) -> Result<(), String> {
+                if idx >= self.len() {
+                    return Err(format!("Index {} out of range for an array of {} elements", idx, self.len()));
+                }
+                (self.write)(v, &mut self.bytes[idx * self.elem_size..(idx + 1) * self.elem_size])
+            }
+            pub fn elem_bytes_mut(&mut self, idx: usize) -> Option<&mut [u8]> {
+                if idx < self.len() {
+                    Some(&mut self.bytes[idx * self.elem_size..(idx + 1) * self.elem_size])
+                } else {
+                    None
+                }
+            }
+            pub fn get_mut<'b>(&'b mut self, idx: usize) -> Option<T::ViewMut> where T: HasViewMut<'b> {
+                self.elem_bytes_mut(idx).map(T::view_mut)
+            }
+            pub fn bytes(&self) -> &[u8] {
+                self.bytes
+            }
+            pub fn bytes_mut(&mut self) -> &mut [u8] {
+                self.bytes
+            }
+        }
+    }
+}
+
+/// The return type of reading the type (with typedefs and modifiers resolved) in a view, and the expression reading it from the byte slice `b`.
+/// Char arrays are read by the policy.
+///
+/// `b` is always sliced to the size of the type out of a view whose length was checked by `new`, so integers, floats and pointers are read directly and can't fail
+fn view_reader(
+    btf: &Btf,
+    ty_id: u32,
+    policy: Option<StringPolicy>,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<(TokenStream, TokenStream)> {
+    let ty_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (de_func, _) = func_names_ident(ty_id);
+    let size_lit = Literal::usize_suffixed(size_resolver.resolve(ty_id));
+    Ok(match btf.type_by_id(ty_id) {
+        BtfType::Int(btf_int) if matches!(btf_int.encoding, BtfIntEncoding::Bool) => {
+            let bit_offset_lit = Literal::usize_suffixed(btf_int.offset as usize);
+            let bits_lit = Literal::usize_suffixed(btf_int.bits as usize);
+            if btf_int.bits == 8 && btf_int.offset == 0 {
+                (quote! { bool }, quote! { b[0] == 1 })
+            } else {
+                (
+                    quote! { bool },
+                    quote! { read_bitfield(b, #bit_offset_lit, #bits_lit) != 0 },
+                )
+            }
+        }
+        BtfType::Int(btf_int) if btf_int.bits % 8 != 0 || btf_int.offset != 0 => {
+            let bit_offset_lit = Literal::usize_suffixed(btf_int.offset as usize);
+            let bits_lit = Literal::usize_suffixed(btf_int.bits as usize);
+            let signed = matches!(btf_int.encoding, BtfIntEncoding::Signed);
+            (
+                quote! { #ty_ident },
+                int_from_raw(
+                    quote! { read_bitfield(b, #bit_offset_lit, #bits_lit) },
+                    btf_int.bits,
+                    signed,
+                )?,
+            )
+        }
+        BtfType::Ptr(_) => (quote! { #ty_ident }, pointer_reader(btf)?),
+        BtfType::Int(_) | BtfType::Float(_) => (
+            quote! { #ty_ident },
+            quote! { #ty_ident::from_ne_bytes(b[..#size_lit].try_into().unwrap()) },
+        ),
+        BtfType::Enum(_) => (quote! { Result<#ty_ident, String> }, quote! { #de_func(b) }),
+        BtfType::Struct(_) | BtfType::Union(_) => {
+            let view_ident = Ident::new(&view_name(ty_id), Span::call_site());
+            (quote! { #view_ident<'a> }, quote! { #view_ident(b) })
+        }
+        BtfType::Array(array) => match policy.or_else(|| strings.array_policy(btf, array)) {
+            // Like the owned binding, an empty array can't hold the NUL a strict string needs
+            Some(StringPolicy::Strict) if array.nelems == 0 => (quote! { &'a [u8] }, quote! { b }),
+            Some(StringPolicy::Strict | StringPolicy::CString) => (
+                quote! { Result<&'a std::ffi::CStr, String> },
+                quote! { std::ffi::CStr::from_bytes_until_nul(b).map_err(|e| e.to_string()) },
+            ),
+            Some(StringPolicy::Lossy | StringPolicy::NulOptional | StringPolicy::Bytes) => (
+                quote! { &'a [u8] },
+                quote! { &b[..b.iter().position(|v| *v == 0).unwrap_or(b.len())] },
+            ),
+            Some(StringPolicy::Raw) => {
+                let elem_count_lit = Literal::usize_suffixed(array.nelems as usize);
+                (
+                    quote! { &'a [u8; #elem_count_lit] },
+                    quote! { b.try_into().unwrap() },
+                )
+            }
+            None => {
+                let elem_ty = lookup_types(btf, array.val_type_id)?;
+                let (elem_ret, elem_expr) =
+                    view_reader(btf, elem_ty, None, size_resolver, strings)?;
+                let elem_size_lit = Literal::usize_suffixed(size_resolver.resolve(elem_ty));
+                (
+                    quote! { ArrayView<'a, #elem_ret> },
+                    quote! { ArrayView::new(b, #elem_size_lit, |b| #elem_expr) },
+                )
+            }
+        },
+        s => bail!("Unsupported type in views: {}", s),
+    })
+}
+
+/// Generate the view of a struct or union: a borrowed byte slice whose length is checked once, with an accessor for every member which only reads that member.
+/// Members of structs are read by methods named after the fields (`f_XX`), and members of unions by `get_XX`.
+/// Nested structs and unions are read as views, arrays as `ArrayView`, and char arrays as `&CStr` or byte slices according to their string policies
+pub(crate) fn generate_view(
+    btf: &Btf,
+    comp: &BtfComposite,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<TokenStream> {
+    let view_ident = Ident::new(&view_name(ty_id), Span::call_site());
+    let ty_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (de_func, _) = func_names_ident(ty_id);
+    let type_size = Literal::usize_suffixed(comp.sz as usize);
+    let kind = if comp.is_struct { "struct" } else { "union" };
+    let mut accessors = TokenStream::new();
+    let mut debug_fields = vec![];
+    for (idx, v) in comp.members.iter().enumerate() {
+        let name = member_name(v.name, idx);
+        let accessor = Ident::new(
+            &if comp.is_struct {
+                format!("f_{}", name)
+            } else {
+                format!("get_{}", name)
+            },
+            Span::call_site(),
+        );
+        let bitfield = resolve_bitfield(btf, v).with_context(|| {
+            anyhow!(
+                "Failed to resolve bitfield for {} `{}` member `{}`",
+                kind,
+                comp.name,
+                v.name
+            )
+        })?;
+        let (ret, body) = if let Some(bitfield) = bitfield {
+            let field_ty = bitfield.field_type()?;
+            let read_expr = bitfield.read(quote! { self.0 })?;
+            if matches!(bitfield.kind, BitfieldKind::Enum { .. }) {
+                (
+                    quote! { Result<#field_ty, String> },
+                    quote! { Ok(#read_expr) },
+                )
+            } else {
+                (field_ty, read_expr)
+            }
+        } else {
+            let real_ty = lookup_types(btf, v.type_id).with_context(|| {
+                anyhow!(
+                    "Failed to lookup type for {} `{}` member `{}`",
+                    kind,
+                    comp.name,
+                    v.name
+                )
+            })?;
+            let policy = strings.member_policy(btf, comp.name, v.name, real_ty);
+            let (ret, expr) = view_reader(btf, real_ty, policy, size_resolver, strings)
+                .with_context(|| {
+                    anyhow!(
+                        "Failed to generate view for {} `{}` member `{}`",
+                        kind,
+                        comp.name,
+                        v.name
+                    )
+                })?;
+            let offset = Literal::usize_suffixed((v.bit_offset / 8) as usize);
+            let size = Literal::usize_suffixed(size_resolver.resolve(v.type_id));
+            (
+                ret,
+                quote! {
+                    let bytes: &'a [u8] = self.0;
+                    let b = &bytes[#offset .. #offset + #size];
+                    #expr
+                },
+            )
+        };
+        accessors.extend(quote! {
+            #[allow(clippy::identity_op, clippy::needless_question_mark)]
+            pub fn #accessor(&self) -> #ret {
+                #body
+            }
+        });
+        if comp.is_struct {
+            let name_lit = Literal::string(&name);
+            debug_fields.push(quote! { .field(#name_lit, &self.#accessor()) });
+        }
+    }
+    Ok(quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy)]
+        pub struct #view_ident<'a>(&'a [u8]);
+        #[allow(unused)]
+        #[allow(non_snake_case)]
+        impl<'a> #view_ident<'a> {
+            pub fn new(b: &'a [u8]) -> Result<Self, String> {
+                if b.len() < #type_size {
+                    return Err(format!("Expected a slice with at least {} bytes, found {}", #type_size, b.len()));
+                }
+                Ok(Self(&b[..#type_size]))
+            }
+            pub fn bytes(&self) -> &'a [u8] {
+                self.0
+            }
+            pub fn decode(&self) -> Result<#ty_ident, String> {
+                #de_func(self.0)
+            }
+            #accessors
+        }
+        impl std::fmt::Debug for #view_ident<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.debug_struct(stringify!(#view_ident))
+                    #(#debug_fields)*
+                    .finish()
+            }
+        }
+    })
+}
+
+/// The expression writing `v`, the owned binding of the type (with typedefs and modifiers resolved), into the byte slice `b` in place without checking it first, if it differs from the encoding function.
+/// Structs, and arrays of them, are written member by member so that their padding is kept, while the owned binding of a union holds all of its bytes
+fn in_place_writer(
+    btf: &Btf,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<Option<TokenStream>> {
+    Ok(match btf.type_by_id(ty_id) {
+        BtfType::Struct(_) => {
+            let view_mut_ident = Ident::new(&view_mut_name(ty_id), Span::call_site());
+            Some(quote! { #view_mut_ident(b).write_members(v) })
+        }
+        BtfType::Array(array) if strings.array_policy(btf, array).is_none() => {
+            let elem_ty = lookup_types(btf, array.val_type_id)?;
+            in_place_writer(btf, elem_ty, size_resolver, strings)?.map(|elem_writer| {
+                // An array of zero-sized elements has no bytes, but the chunk size must not be zero
+                let chunk_size = Literal::usize_suffixed(size_resolver.resolve(elem_ty).max(1));
+                quote! {
+                    {
+                        for (v, b) in v.iter().zip(b.chunks_exact_mut(#chunk_size)) {
+                            #elem_writer?;
+                        }
+                        Ok::<(), String>(())
+                    }
+                }
+            })
+        }
+        _ => None,
+    })
+}
+
+/// The function writing an owned binding of the type into a byte slice in place. Values are checked by encoding them into a scratch buffer first, so a failed write leaves the bytes unchanged
+fn checked_writer(
+    btf: &Btf,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<TokenStream> {
+    let ty_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (_, ser_func) = func_names_ident(ty_id);
+    let enc_func = encode_func_ident(&ser_func);
+    Ok(match in_place_writer(btf, ty_id, size_resolver, strings)? {
+        Some(writer) => {
+            let size = Literal::usize_suffixed(size_resolver.resolve(ty_id));
+            quote! {
+                |v: &#ty_ident, b: &mut [u8]| -> Result<(), String> {
+                    #enc_func(v, &mut [0u8; #size])?;
+                    #writer
+                }
+            }
+        }
+        None => quote! { #enc_func },
+    })
+}
+
+/// Generate the mutable view of a struct or union, which writes members into the borrowed bytes in place, leaving all other bytes (including padding) untouched.
+/// Every member gets a setter `set_XX` taking the owned binding of the member, and `set` writes the whole struct or union. Nested structs are written member by member, so their padding is kept as well.
+/// Nested structs and unions are also reachable as mutable views (by `f_XX` for structs and `get_XX` for unions), and arrays as `ArrayViewMut`
+pub(crate) fn generate_view_mut(
+    btf: &Btf,
+    comp: &BtfComposite,
+    ty_id: u32,
+    size_resolver: &mut SizeResolveCache,
+    strings: &StringPolicies,
+) -> Result<TokenStream> {
+    let view_mut_ident = Ident::new(&view_mut_name(ty_id), Span::call_site());
+    let view_ident = Ident::new(&view_name(ty_id), Span::call_site());
+    let ty_ident = Ident::new(&ty_name(ty_id), Span::call_site());
+    let (_, ser_func) = func_names_ident(ty_id);
+    let enc_func = encode_func_ident(&ser_func);
+    let type_size = Literal::usize_suffixed(comp.sz as usize);
+    let kind = if comp.is_struct { "struct" } else { "union" };
+    let mut accessors = TokenStream::new();
+    let mut member_writes = vec![];
+    for (idx, v) in comp.members.iter().enumerate() {
+        let name = member_name(v.name, idx);
+        let setter = Ident::new(&format!("set_{}", name), Span::call_site());
+        let field_ident = Ident::new(&format!("f_{}", name), Span::call_site());
+        let bitfield = resolve_bitfield(btf, v).with_context(|| {
+            anyhow!(
+                "Failed to resolve bitfield for {} `{}` member `{}`",
+                kind,
+                comp.name,
+                v.name
+            )
+        })?;
+        if let Some(bitfield) = bitfield {
+            let field_ty = bitfield.field_type()?;
+            let write_stmt = bitfield.write(quote! { &mut self.0[..] }, quote! { v })?;
+            accessors.extend(quote! {
+                pub fn #setter(&mut self, v: &#field_ty) -> Result<(), String> {
+                    #write_stmt
+                    Ok(())
+                }
+            });
+            member_writes
+                .push(bitfield.write(quote! { &mut self.0[..] }, quote! { &v.#field_ident })?);
+            continue;
+        }
+        let real_ty = lookup_types(btf, v.type_id).with_context(|| {
+            anyhow!(
+                "Failed to lookup type for {} `{}` member `{}`",
+                kind,
+                comp.name,
+                v.name
+            )
+        })?;
+        let (type_ident, (_, ser_func)) = strings.member_type(btf, comp.name, v.name, real_ty);
+        let member_enc_func = encode_func_ident(&ser_func);
+        let offset = Literal::usize_suffixed((v.bit_offset / 8) as usize);
+        let size = Literal::usize_suffixed(size_resolver.resolve(v.type_id));
+        let policy = strings.member_policy(btf, comp.name, v.name, real_ty);
+        let writer = if policy.is_none() {
+            in_place_writer(btf, real_ty, size_resolver, strings)?
+        } else {
+            None
+        };
+        if let Some(writer) = writer {
+            accessors.extend(quote! {
+                #[allow(clippy::identity_op)]
+                pub fn #setter(&mut self, v: &#type_ident) -> Result<(), String> {
+                    #member_enc_func(v, &mut [0u8; #size])?;
+                    let b = &mut self.0[#offset .. #offset + #size];
+                    #writer
+                }
+            });
+            member_writes.push(quote! {
+                {
+                    let v = &v.#field_ident;
+                    let b = &mut self.0[#offset .. #offset + #size];
+                    #writer?;
+                }
+            });
+        } else {
+            accessors.extend(quote! {
+                #[allow(clippy::identity_op)]
+                pub fn #setter(&mut self, v: &#type_ident) -> Result<(), String> {
+                    #member_enc_func(v, &mut self.0[#offset .. #offset + #size])
+                }
+            });
+            member_writes.push(quote! {
+                #member_enc_func(&v.#field_ident, &mut self.0[#offset .. #offset + #size])?;
+            });
+        }
+        let accessor = Ident::new(
+            &if comp.is_struct {
+                format!("f_{}", name)
+            } else {
+                format!("get_{}", name)
+            },
+            Span::call_site(),
+        );
+        match btf.type_by_id(real_ty) {
+            BtfType::Struct(_) | BtfType::Union(_) => {
+                let nested_ident = Ident::new(&view_mut_name(real_ty), Span::call_site());
+                accessors.extend(quote! {
+                    #[allow(clippy::identity_op)]
+                    pub fn #accessor(&mut self) -> #nested_ident<'_> {
+                        #nested_ident(&mut self.0[#offset .. #offset + #size])
+                    }
+                });
+            }
+            BtfType::Array(array) if policy.is_none() => {
+                let elem_ty = lookup_types(btf, array.val_type_id)?;
+                let elem_ident = Ident::new(&ty_name(elem_ty), Span::call_site());
+                let elem_writer = checked_writer(btf, elem_ty, size_resolver, strings)?;
+                let elem_size = Literal::usize_suffixed(size_resolver.resolve(elem_ty));
+                accessors.extend(quote! {
+                    #[allow(clippy::identity_op)]
+                    pub fn #accessor(&mut self) -> ArrayViewMut<'_, #elem_ident> {
+                        ArrayViewMut::new(&mut self.0[#offset .. #offset + #size], #elem_size, #elem_writer)
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+    // The owned binding of a union holds all of its bytes, so it's written as a whole
+    let whole_setter = if comp.is_struct {
+        quote! {
+            pub fn set(&mut self, v: &#ty_ident) -> Result<(), String> {
+                #enc_func(v, &mut [0u8; #type_size])?;
+                self.write_members(v)
+            }
+            #[allow(clippy::identity_op)]
+            fn write_members(&mut self, v: &#ty_ident) -> Result<(), String> {
+                #(#member_writes)*
+                Ok(())
+            }
+        }
+    } else {
+        quote! {
+            pub fn set(&mut self, v: &#ty_ident) -> Result<(), String> {
+                #enc_func(v, self.0)
+            }
+        }
+    };
+    Ok(quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub struct #view_mut_ident<'a>(&'a mut [u8]);
+        impl<'b> HasViewMut<'b> for #ty_ident {
+            type ViewMut = #view_mut_ident<'b>;
+            fn view_mut(b: &'b mut [u8]) -> Self::ViewMut {
+                #view_mut_ident(b)
+            }
+        }
+        #[allow(unused)]
+        #[allow(non_snake_case)]
+        impl<'a> #view_mut_ident<'a> {
+            pub fn new(b: &'a mut [u8]) -> Result<Self, String> {
+                if b.len() < #type_size {
+                    return Err(format!("Expected a slice with at least {} bytes, found {}", #type_size, b.len()));
+                }
+                Ok(Self(&mut b[..#type_size]))
+            }
+            pub fn view(&self) -> #view_ident<'_> {
+                #view_ident(self.0)
+            }
+            pub fn bytes(&self) -> &[u8] {
+                self.0
+            }
+            pub fn bytes_mut(&mut self) -> &mut [u8] {
+                self.0
+            }
+            #whole_setter
+            #accessors
+        }
+        impl std::fmt::Debug for #view_mut_ident<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                self.view().fmt(f)
+            }
+        }
+    })
+}
+
+/// The top level alias of the view (or the mutable view) of a named struct or union, like `SView` (or `SViewMut`) for `S`
+pub(crate) fn generate_view_alias(comp: &BtfComposite, ty_id: u32, mutable: bool) -> TokenStream {
+    let (alias, view_ident) = if mutable {
+        (format!("{}ViewMut", comp.name), view_mut_name(ty_id))
+    } else {
+        (format!("{}View", comp.name), view_name(ty_id))
+    };
+    let alias = Ident::new(&alias, Span::call_site());
+    let view_ident = Ident::new(&view_ident, Span::call_site());
+    quote! {
+        #[allow(unused)]
+        #[allow(non_camel_case_types)]
+        pub type #alias<'a> = inner_impl:: #view_ident<'a>;
+    }
+}
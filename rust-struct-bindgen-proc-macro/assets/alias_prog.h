@@ -0,0 +1,20 @@
+enum Level {
+    L_LOW,
+    L_MID,
+    L_HIGH,
+    L_DEFAULT = L_MID,
+    L_MAX = L_HIGH
+};
+
+enum State {
+    ST_OFF = 0,
+    ST_DISABLED = 0,
+    ST_ON = 1
+};
+
+struct AL {
+    enum Level lv;
+    enum State st;
+};
+
+struct AL* __dummy;
@@ -0,0 +1,23 @@
+#include "bitfield_prog.h"
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+int main() {
+    struct BH st;
+    memset(&st, 0, sizeof(st));
+    st.bf.a = 5;
+    st.bf.b = -7;
+    st.bf.c = 0xab;
+    st.bf.flag = 1;
+    st.bf.big = 0x123456789aULL;
+    st.bf.m = M_C;
+    st.bf.s = -200;
+    st.bf.tail = 0xdeadbeef;
+    st.bu.whole = 0x1234567d;
+    FILE* fp = fopen("bitfield_dump.bin", "w");
+    assert(fp != NULL);
+    fwrite(&st, sizeof(st), 1, fp);
+    fclose(fp);
+
+    return 0;
+}
@@ -0,0 +1,28 @@
+enum Mode {
+    M_A,
+    M_B,
+    M_C
+};
+
+struct BF {
+    unsigned int a : 3;
+    int b : 5;
+    unsigned char c;
+    _Bool flag : 1;
+    unsigned long long big : 40;
+    enum Mode m : 2;
+    short s : 9;
+    unsigned int tail;
+};
+
+union BU {
+    unsigned int whole;
+    unsigned int low : 4;
+};
+
+struct BH {
+    struct BF bf;
+    union BU bu;
+};
+
+struct BH* __dummy;
@@ -0,0 +1,11 @@
+typedef unsigned char __u8;
+
+struct PKT {
+    unsigned char mac[6];
+    __u8 key[4];
+    __u8 label[8];
+    signed char sname[4];
+    char name[6];
+};
+
+struct PKT* __dummy;
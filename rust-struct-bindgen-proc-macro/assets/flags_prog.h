@@ -0,0 +1,20 @@
+enum Perm {
+    P_READ = 1,
+    P_WRITE = 2,
+    P_EXEC = 4
+};
+
+enum Opt {
+    O_NONE = 0,
+    O_A = 1,
+    O_B = 2,
+    O_ALL = 3
+};
+
+struct FL {
+    enum Perm perm;
+    enum Opt opt;
+    enum Perm small : 3;
+};
+
+struct FL* __dummy;
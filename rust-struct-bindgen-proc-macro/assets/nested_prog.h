@@ -0,0 +1,12 @@
+struct P {
+    int x;
+    char c;
+};
+
+struct NS {
+    struct P one;
+    struct P arr[2];
+    short tail;
+};
+
+struct NS* __dummy;
@@ -0,0 +1,7 @@
+struct PP {
+    int tag;
+    void *ptr;
+    unsigned int tail;
+};
+
+struct PP* __dummy;
@@ -0,0 +1,12 @@
+enum Dir {
+    D_BACK = -1,
+    D_STOP = 0,
+    D_FWD = 1
+};
+
+struct SBF {
+    enum Dir d : 2;
+    unsigned int rest : 30;
+};
+
+struct SBF* __dummy;
@@ -0,0 +1,13 @@
+struct STR {
+    char comm[16];
+    char name[8];
+    char tail[0];
+};
+
+union USTR {
+    char text[8];
+    unsigned long long num;
+};
+
+struct STR* __dummy1;
+union USTR* __dummy2;
@@ -0,0 +1,23 @@
+#include "typedef_prog.h"
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+int main() {
+    struct named st;
+    memset(&st, 0, sizeof(st));
+    *(int*)&st.x = -1;
+    st.y = 0x12345678;
+    for (int i = 0; i < 3; i++) {
+        st.arr[i] = i + 1;
+    }
+    *(int*)&st.carr[0] = -10;
+    *(int*)&st.carr[1] = -20;
+    st.in.a = 0xdeadbeef;
+    st.in.b = -3;
+    FILE* fp = fopen("typedef_dump.bin", "w");
+    assert(fp != NULL);
+    fwrite(&st, sizeof(st), 1, fp);
+    fclose(fp);
+
+    return 0;
+}
@@ -0,0 +1,21 @@
+typedef unsigned int __u32;
+typedef int pid_t;
+typedef pid_t tid_t;
+
+typedef struct {
+    __u32 a;
+    short b;
+} anon_t;
+
+typedef struct named named;
+
+struct named {
+    const int x;
+    volatile __u32 y;
+    pid_t arr[3];
+    const tid_t carr[2];
+    anon_t in;
+    __u32 * restrict ptr;
+};
+
+struct named* __dummy;
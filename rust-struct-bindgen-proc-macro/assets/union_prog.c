@@ -0,0 +1,17 @@
+#include "union_prog.h"
+#include <stdio.h>
+#include <string.h>
+#include <assert.h>
+int main() {
+    struct T st;
+    memset(&st, 0, sizeof(st));
+    st.tag = 1;
+    st.u.ull = 0x1122334455667788;
+    st.raw = 0x12345678;
+    FILE* fp = fopen("union_dump.bin", "w");
+    assert(fp != NULL);
+    fwrite(&st, sizeof(st), 1, fp);
+    fclose(fp);
+
+    return 0;
+}
@@ -0,0 +1,20 @@
+union U {
+    int i;
+    float f;
+    unsigned long long ull;
+    short arr[4];
+};
+
+struct T {
+    int tag;
+    union U u;
+    union {
+        unsigned int raw;
+        struct {
+            unsigned short lo;
+            unsigned short hi;
+        } half;
+    };
+};
+
+struct T* __dummy;
@@ -0,0 +1,19 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+//! Prepare the inputs of the `out_dir = "..."` and `env = "..."` path forms for the tests
+use std::path::PathBuf;
+
+fn main() {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::copy(
+        "assets/union_prog.bpf.o",
+        out_dir.join("union_prog_out_dir.bpf.o"),
+    )
+    .unwrap();
+    println!("cargo:rustc-env=UNION_PROG_BPF_O=assets/union_prog.bpf.o");
+    println!("cargo:rerun-if-changed=assets/union_prog.bpf.o");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,53 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use rust_struct_bindgen_impl::BindgenOptions;
+use syn::{
+    parse::{Parse, ParseStream},
+    Ident, Lit, Token,
+};
+
+use crate::path::SpannedInputPath;
+
+/// Arguments of the bindgen macros, like `"xxx.bpf.o", allowlist_type = "event_.*"`. See `SpannedInputPath` for the forms of the path
+///
+/// Every `key = value` pair is passed to `BindgenOptions::set`
+pub(crate) struct MacroArgs {
+    pub(crate) file_path: SpannedInputPath,
+    pub(crate) options: BindgenOptions,
+}
+
+impl Parse for MacroArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut result = MacroArgs {
+            file_path: input.parse()?,
+            options: BindgenOptions::default(),
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value = match input.parse::<Lit>()? {
+                Lit::Str(s) => s.value(),
+                Lit::Bool(b) => b.value.to_string(),
+                Lit::Int(i) => i.base10_digits().to_string(),
+                s => {
+                    return Err(syn::Error::new(
+                        s.span(),
+                        "Expected a string, bool or integer",
+                    ))
+                }
+            };
+            result
+                .options
+                .set(&key.to_string(), &value)
+                .map_err(|e| syn::Error::new(key.span(), e))?;
+        }
+        Ok(result)
+    }
+}
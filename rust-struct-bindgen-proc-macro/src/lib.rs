@@ -3,59 +3,91 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
+use anyhow::{anyhow, Context};
+use args::MacroArgs;
 use proc_macro::TokenStream;
-use rust_struct_bindgen_impl::{
-    btf::types::Btf, generate_bindgen_token_stream, helper::create_elf_with_btf_section,
-    object::ElfFile,
-};
-use std::path::PathBuf;
-use syn::{parse_macro_input, LitStr};
+use quote::quote;
+use rust_struct_bindgen_impl::{helper::load_btf, Builder};
+use syn::parse_macro_input;
+
+mod args;
+mod path;
+
 /// Generate binding source codes for the BTF info in the provided ELF
 ///
 /// The calling syntax should be:
-/// ```rust
+/// ```ignore
 /// btf_struct_bindgen_with_elf!("xxx.bpf.o");
 /// ```
 ///
-/// Where `xxx.bpf.o` is file path relatived to the `CARGO_MANIFEST_DIR`, aka the directory where `Cargo.toml` of you project exists
+/// Options of the generator (see `rust_struct_bindgen_impl::BindgenOptions`) can be given as `key = value` after the path. E.g. types to generate can be selected with `allowlist_type` and `blocklist_type`, which accept regexes matching the whole type name and can be given multiple times:
+/// ```ignore
+/// btf_struct_bindgen_with_elf!("xxx.bpf.o", allowlist_type = "event_.*", blocklist_type = "event_internal");
+/// ```
+///
+/// Where `xxx.bpf.o` is file path relatived to the `CARGO_MANIFEST_DIR`, aka the directory where `Cargo.toml` of you project exists, unless it's absolute. The file is tracked by cargo, so the bindings are regenerated once it changes
+///
+/// Files built by a build script can be referred with `concat!` and `env!`, or with `out_dir` (relative to `OUT_DIR`) and `env` (the path held by an environment variable):
+/// ```ignore
+/// btf_struct_bindgen_with_elf!(concat!(env!("OUT_DIR"), "/xxx.bpf.o"));
+/// btf_struct_bindgen_with_elf!(out_dir = "xxx.bpf.o");
+/// btf_struct_bindgen_with_elf!(env = "BPF_OBJECT_PATH");
+/// ```
 #[proc_macro]
 pub fn btf_struct_bindgen_with_elf(input: TokenStream) -> TokenStream {
-    let file_path = parse_macro_input!(input as LitStr);
-    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-    let btf_file_path = root.join(file_path.value());
-    let buf = std::fs::read(&btf_file_path).unwrap_or_else(|_| {
-        panic!(
-            "Failed to read the elf file {}",
-            btf_file_path.to_str().unwrap()
-        )
-    });
-    let object = ElfFile::parse(&buf).expect("Failed to parse file as ELF");
-    let btf_data = Btf::load(&object).expect("Failed to parse BTF");
-    let stream = generate_bindgen_token_stream(&btf_data).unwrap();
-    stream.into()
+    let args = parse_macro_input!(input as MacroArgs);
+    expand(&args, false)
 }
 /// Generate binding source codes for the provided BTF archive
 ///
 /// The calling syntax should be:
-/// ```rust
+/// ```ignore
 /// btf_struct_bindgen_with_btf!("xxx.btf");
 /// ```
 ///
-/// Where `xxx.bpf.o` is file path relatived to the `CARGO_MANIFEST_DIR`, aka the directory where `Cargo.toml` of you project exists
+/// Options of the generator (see `rust_struct_bindgen_impl::BindgenOptions`) can be given as `key = value` after the path. E.g. types to generate can be selected with `allowlist_type` and `blocklist_type`, which accept regexes matching the whole type name and can be given multiple times:
+/// ```ignore
+/// btf_struct_bindgen_with_btf!("xxx.btf", allowlist_type = "event_.*", blocklist_type = "event_internal");
+/// ```
+///
+/// Where `xxx.btf` is file path relatived to the `CARGO_MANIFEST_DIR`, aka the directory where `Cargo.toml` of you project exists, unless it's absolute. The file is tracked by cargo, so the bindings are regenerated once it changes
+///
+/// Files built by a build script can be referred with `concat!` and `env!`, or with `out_dir` (relative to `OUT_DIR`) and `env` (the path held by an environment variable):
+/// ```ignore
+/// btf_struct_bindgen_with_btf!(concat!(env!("OUT_DIR"), "/xxx.btf"));
+/// btf_struct_bindgen_with_btf!(out_dir = "xxx.btf");
+/// btf_struct_bindgen_with_btf!(env = "BPF_OBJECT_PATH");
+/// ```
 #[proc_macro]
 pub fn btf_struct_bindgen_with_btf(input: TokenStream) -> TokenStream {
-    let file_path = parse_macro_input!(input as LitStr);
-    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-    let btf_file_path = root.join(file_path.value());
-    let buf = std::fs::read(&btf_file_path).unwrap_or_else(|_| {
-        panic!(
-            "Failed to read btf file {}",
-            btf_file_path.to_str().unwrap()
-        )
-    });
-    let buf = create_elf_with_btf_section(&buf[..], true).unwrap();
-    let object = ElfFile::parse(&buf).expect("Failed to parse file as ELF");
-    let btf_data = Btf::load(&object).expect("Failed to parse BTF");
-    let stream = generate_bindgen_token_stream(&btf_data).unwrap();
-    stream.into()
+    let args = parse_macro_input!(input as MacroArgs);
+    expand(&args, true)
+}
+
+/// Run the generator, turning any failure into a `compile_error!` pointing at the path literal
+fn expand(args: &MacroArgs, is_btf_archive: bool) -> TokenStream {
+    match generate(args, is_btf_archive) {
+        Ok(stream) => stream.into(),
+        Err(e) => syn::Error::new(args.file_path.span, format!("{:#}", e))
+            .to_compile_error()
+            .into(),
+    }
+}
+
+fn generate(args: &MacroArgs, is_btf_archive: bool) -> anyhow::Result<proc_macro2::TokenStream> {
+    let file_path = args.file_path.path.resolve()?;
+    let buf = std::fs::read(&file_path)
+        .with_context(|| anyhow!("Failed to read {}", file_path.display()))?;
+    let btf_data = load_btf(&buf, is_btf_archive, None).context("Failed to parse BTF")?;
+    let stream = Builder::with_options(args.options.clone())
+        .generate(&btf_data)
+        .with_context(|| anyhow!("Failed to generate bindings"))?;
+    // Cargo doesn't know about files read by proc macros. Including the file makes the expansion depend on it, so bindings are regenerated once it changes
+    let tracked_path = file_path
+        .to_str()
+        .ok_or_else(|| anyhow!("Path {} is not valid UTF-8", file_path.display()))?;
+    Ok(quote! {
+        const _: &[u8] = include_bytes!(#tracked_path);
+        #stream
+    })
 }
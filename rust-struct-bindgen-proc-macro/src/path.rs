@@ -0,0 +1,130 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use proc_macro2::Span;
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Expr, ExprLit, ExprMacro, Ident, Lit, LitStr, Token,
+};
+
+/// The file passed to the bindgen macros
+pub(crate) enum InputPath {
+    /// A path, which is relative to `CARGO_MANIFEST_DIR` unless absolute. It's either a literal, or a `concat!`/`env!` expression evaluated when expanding
+    Path(String),
+    /// `out_dir = "xxx.bpf.o"`, a path relative to `OUT_DIR`
+    OutDir(String),
+    /// `env = "VAR"`, a path held by the environment variable
+    Env(String),
+}
+
+/// The input path with the span used for reporting errors
+pub(crate) struct SpannedInputPath {
+    pub(crate) path: InputPath,
+    pub(crate) span: Span,
+}
+
+impl Parse for SpannedInputPath {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            let lit: LitStr = input.parse()?;
+            return Ok(Self {
+                path: InputPath::Path(lit.value()),
+                span: lit.span(),
+            });
+        }
+        if input.peek(Ident) && input.peek2(Token![=]) {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+            let path = match key.to_string().as_str() {
+                "out_dir" => InputPath::OutDir(value.value()),
+                "env" => InputPath::Env(value.value()),
+                s => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!(
+                            "Expected the file path, or `out_dir = \"...\"`, or `env = \"...\"`, found `{}`",
+                            s
+                        ),
+                    ))
+                }
+            };
+            return Ok(Self {
+                path,
+                span: value.span(),
+            });
+        }
+        let expr: ExprMacro = input.parse()?;
+        Ok(Self {
+            path: InputPath::Path(eval_string_macro(&expr)?),
+            span: expr.span(),
+        })
+    }
+}
+
+/// Evaluate `concat!` and `env!` with string literal arguments, as the compiler does
+fn eval_string_macro(expr: &ExprMacro) -> syn::Result<String> {
+    let args = expr
+        .mac
+        .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)?;
+    let strings = args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }) => Ok(s.value()),
+            Expr::Macro(inner) => eval_string_macro(inner),
+            s => Err(syn::Error::new(
+                s.span(),
+                "Expected a string literal, `concat!` or `env!`",
+            )),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    if expr.mac.path.is_ident("concat") {
+        Ok(strings.concat())
+    } else if expr.mac.path.is_ident("env") {
+        let [var, ..] = &strings[..] else {
+            return Err(syn::Error::new(
+                expr.span(),
+                "`env!` expects the name of the variable",
+            ));
+        };
+        std::env::var(var).map_err(|_| {
+            syn::Error::new(
+                expr.span(),
+                format!("Environment variable `{}` is not defined", var),
+            )
+        })
+    } else {
+        Err(syn::Error::new(
+            expr.mac.path.span(),
+            "Only `concat!` and `env!` are supported in the file path",
+        ))
+    }
+}
+
+impl InputPath {
+    /// Resolve the absolute path of the file
+    pub(crate) fn resolve(&self) -> anyhow::Result<PathBuf> {
+        let env_var =
+            |name: &str| std::env::var(name).with_context(|| anyhow!("Failed to read `{}`", name));
+        let relative_to_manifest = |path: &str| -> anyhow::Result<PathBuf> {
+            Ok(PathBuf::from(env_var("CARGO_MANIFEST_DIR")?).join(path))
+        };
+        Ok(match self {
+            InputPath::Path(path) => relative_to_manifest(path)?,
+            InputPath::OutDir(path) => PathBuf::from(
+                env_var("OUT_DIR").context("`out_dir` requires a build script of the crate")?,
+            )
+            .join(path),
+            InputPath::Env(var) => relative_to_manifest(&env_var(var)?)?,
+        })
+    }
+}
@@ -9,7 +9,7 @@ mod util;
 
 #[test]
 fn test_deserializing() {
-    let bin_data = std::fs::read(util::get_assets_dir().join("dumper_test.bin")).unwrap();
+    let bin_data = util::read_asset("dumper_test.bin");
     let st = bindgen::S::from_bytes(&bin_data).unwrap();
     assert_eq!(st.f_str, "A-String");
     for i in 0..2 {
@@ -36,8 +36,9 @@ fn test_deserializing() {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn test_serializing() {
-    let bin_data = std::fs::read(util::get_assets_dir().join("dumper_test.bin")).unwrap();
+    let bin_data = util::read_asset("dumper_test.bin");
     // let st = bindgen::S::from_bytes(&bin_data).unwrap();
     let mut arr1 = [[[0i32; 4]; 3]; 2];
     for i in 0..2 {
@@ -71,3 +72,26 @@ fn test_serializing() {
     // Ok to directly compare bytes, since we ensured the unused area of the binary is filled with zero, in both `simple_prog.c` and here
     assert_eq!(ser_bytes, bin_data);
 }
+
+#[test]
+fn test_encode_into() {
+    let bin_data = util::read_asset("dumper_test.bin");
+    let st = bindgen::S::from_bytes(&bin_data).unwrap();
+    // Stale bytes in the buffer, including padding, are overwritten
+    let mut buf = vec![0xffu8; bin_data.len()];
+    st.encode_into(&mut buf).unwrap();
+    assert_eq!(buf, bin_data);
+    assert_eq!(st.to_bytes().unwrap(), buf);
+    // The slice must be exactly as large as the type
+    assert!(st.encode_into(&mut buf[1..]).is_err());
+    let mut longer = vec![0u8; bin_data.len() + 1];
+    assert!(st.encode_into(&mut longer).is_err());
+    // A field that can't be encoded fails the whole value
+    let mut st = st;
+    st.f_str = "a string longer than the array".into();
+    assert!(st.encode_into(&mut buf).is_err());
+
+    let mut buf = [0u8; 4];
+    E::E_B.encode_into(&mut buf).unwrap();
+    assert_eq!(buf, 1u32.to_ne_bytes());
+}
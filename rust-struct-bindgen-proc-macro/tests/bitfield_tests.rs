@@ -0,0 +1,97 @@
+use rust_struct_bindgen_impl::{
+    btf::types::Btf,
+    dynamic::{decode, encode, Value},
+};
+
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/bitfield_prog.bpf.o");
+}
+
+mod signed_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+    btf_struct_bindgen_with_btf!("assets/signed_bitfield_prog.btf");
+}
+
+mod util;
+
+#[test]
+fn test_bitfield_deserializing() {
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let st = bindgen::BH::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_bf.f_a, 5u8);
+    assert_eq!(st.f_bf.f_b, -7i8);
+    assert_eq!(st.f_bf.f_c, 0xab);
+    assert!(st.f_bf.f_flag);
+    assert_eq!(st.f_bf.f_big, 0x123456789au64);
+    assert!(matches!(st.f_bf.f_m, bindgen::Mode::M_C));
+    assert_eq!(st.f_bf.f_s, -200i16);
+    assert_eq!(st.f_bf.f_tail, 0xdeadbeef);
+    assert_eq!(st.f_bu.get_whole().unwrap(), 0x1234567d);
+    assert_eq!(st.f_bu.get_low().unwrap(), 0xd);
+}
+
+#[test]
+fn test_bitfield_serializing() {
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let mut st = bindgen::BH::from_bytes(&bin_data).unwrap();
+    st.f_bf = bindgen::BF {
+        f_a: 5,
+        f_b: -7,
+        f_c: 0xab,
+        f_flag: true,
+        f_big: 0x123456789a,
+        f_m: bindgen::Mode::M_C,
+        f_s: -200,
+        f_tail: 0xdeadbeef,
+    };
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    // Bits not covered by any bitfield are cleared
+    let mut buf = vec![0xffu8; bin_data.len()];
+    st.encode_into(&mut buf).unwrap();
+    assert_eq!(buf, bin_data);
+
+    st.f_bu.set_low(&0x3).unwrap();
+    assert_eq!(st.f_bu.get_whole().unwrap(), 0x12345673);
+    assert!(st.f_bu.set_low(&0x10).is_err());
+
+    st.f_bf.f_b = 16;
+    assert!(st.to_bytes().is_err());
+}
+
+#[test]
+fn test_signed_enum_bitfield() {
+    use signed_bindgen::{Dir, SBF};
+    // `D_BACK = -1` is stored as `0b11` in the 2 bits of `d`
+    let bin_data = if cfg!(target_endian = "little") {
+        ((5u32 << 2) | 0b11).to_ne_bytes()
+    } else {
+        ((0b11u32 << 30) | 5).to_ne_bytes()
+    };
+    let st = SBF::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_d, Dir::D_BACK);
+    assert_eq!(st.f_rest, 5);
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    assert_eq!(
+        signed_bindgen::SBFView::new(&bin_data)
+            .unwrap()
+            .f_d()
+            .unwrap(),
+        Dir::D_BACK
+    );
+
+    let btf_data = util::read_asset("signed_bitfield_prog.btf");
+    let btf = Btf::from_bytes(&btf_data).unwrap();
+    let value = decode(&btf, "SBF", &bin_data).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("SBF should be a struct");
+    };
+    assert_eq!(
+        fields[0].1,
+        Value::Enum {
+            name: "D_BACK".into(),
+            value: -1
+        }
+    );
+    assert_eq!(encode(&btf, "SBF", &value).unwrap(), bin_data);
+}
@@ -0,0 +1,162 @@
+use rust_struct_bindgen_impl::{
+    btf::types::Btf,
+    dynamic::{decode, Value},
+    generate_bindgen_token_stream, Builder,
+};
+
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+    btf_struct_bindgen_with_btf!("assets/union_prog.be.btf");
+}
+
+mod pointer32_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/pointer32_prog.bpf.o");
+}
+
+mod util;
+
+#[test]
+fn test_btf_archive_macro() {
+    let bin_data = util::read_asset("union_dump.bin");
+    let st = bindgen::T::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_u.get_ull().unwrap(), 0x1122334455667788);
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+}
+
+#[test]
+fn test_raw_btf_matches_elf() {
+    let generate = |btf: &Btf| generate_bindgen_token_stream(btf).unwrap().to_string();
+    let elf_data = util::read_asset("union_prog.bpf.o");
+    let from_elf = Btf::from_elf_bytes(&elf_data).unwrap();
+    let expected = generate(&from_elf);
+
+    for name in ["union_prog.btf", "union_prog.be.btf"] {
+        let data = util::read_asset(name);
+        let btf = Btf::from_bytes(&data).unwrap();
+        assert_eq!(btf.type_cnt(), from_elf.type_cnt(), "{}", name);
+        assert_eq!(btf.ptr_sz(), 8, "{}", name);
+        assert_eq!(generate(&btf), expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_4_bytes_pointers() {
+    use pointer32_bindgen::{PPView, PP};
+    let mut bin_data = 1i32.to_ne_bytes().to_vec();
+    bin_data.extend(0x12345678u32.to_ne_bytes());
+    bin_data.extend(2u32.to_ne_bytes());
+    let pp = PP::from_bytes(&bin_data).unwrap();
+    assert_eq!(pp.f_ptr, 0x12345678u64);
+    assert_eq!(pp.f_tail, 2);
+    assert_eq!(pp.to_bytes().unwrap(), bin_data);
+    assert_eq!(PPView::new(&bin_data).unwrap().f_ptr(), 0x12345678u64);
+    // Addresses not fitting in 4 bytes are rejected
+    let pp = PP {
+        f_ptr: 1 << 32,
+        ..pp
+    };
+    assert!(pp.to_bytes().is_err());
+
+    let elf_data = util::read_asset("pointer32_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    assert_eq!(btf.ptr_sz(), 4);
+    let Value::Struct(fields) = decode(&btf, "PP", &bin_data).unwrap() else {
+        panic!("PP should be a struct");
+    };
+    assert_eq!(fields[1].1, Value::Pointer(0x12345678));
+}
+
+#[test]
+fn test_malformed_btf() {
+    let data = util::read_asset("union_prog.btf");
+    // Truncated data is rejected instead of panicking
+    for len in [0, 10, 24, 100, data.len() - 1] {
+        assert!(Btf::from_bytes(&data[..len]).is_err(), "{}", len);
+    }
+    let mut bad_magic = data.clone();
+    bad_magic[0] = 0;
+    let err = Btf::from_bytes(&bad_magic).unwrap_err().to_string();
+    assert!(err.contains("Invalid BTF magic"), "{}", err);
+    // A btf archive is not an ELF file
+    assert!(Btf::from_elf_bytes(&data).is_err());
+
+    // Types after `int` (type 1), given as words
+    let raw_btf = |types: &[u32]| {
+        let strs = b"\0int\0";
+        let mut out = vec![0x9f, 0xeb, 1, 0];
+        let type_len = (types.len() as u32 + 4) * 4;
+        for v in [24, 0, type_len, type_len, strs.len() as u32] {
+            out.extend(v.to_le_bytes());
+        }
+        for v in [1, 1 << 24, 4, 32].iter().chain(types) {
+            out.extend(v.to_le_bytes());
+        }
+        out.extend(strs);
+        out
+    };
+    let typedef = |type_id: u32| [1, 8 << 24, type_id];
+    let array = |val: u32, nelems: u32| [0, 3 << 24, 0, val, 1, nelems];
+    let valid = raw_btf(&[&typedef(1)[..], &array(2, 3)].concat());
+    let btf = Btf::from_bytes(&valid).unwrap();
+    assert_eq!(btf.get_size_of(3), 12);
+    // Invalid type ids are rejected when loading, so that `type_by_id` can't panic on them
+    let err = Btf::from_bytes(&raw_btf(&typedef(9))).unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("refers to invalid type id 9"),
+        "{:#}",
+        err
+    );
+    let err = Btf::from_bytes(&raw_btf(&array(9, 1))).unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("refers to invalid type id 9"),
+        "{:#}",
+        err
+    );
+    // So are sizes overflowing, and loops of typedefs
+    for types in [array(1, 1 << 30).to_vec(), typedef(2).to_vec()] {
+        let err = Btf::from_bytes(&raw_btf(&types)).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Size of BTF type 2"),
+            "{:#}",
+            err
+        );
+    }
+}
+
+#[test]
+fn test_split_btf() {
+    let base_data = util::read_asset("union_prog.btf");
+    let split_data = util::read_asset("union_prog_split.btf");
+    let base = Btf::from_bytes(&base_data).unwrap();
+    let btf = Btf::from_split_bytes(&split_data, &base).unwrap();
+    assert!(btf.is_split());
+    assert_eq!(btf.start_id(), base.type_cnt());
+    assert_eq!(btf.type_cnt(), base.type_cnt() + 3);
+    // Names of the split types come from the split string section
+    assert_eq!(btf.type_by_id(btf.start_id()).name(), "mode");
+    assert!(Btf::from_bytes(&split_data).is_err());
+
+    // Only the base types referred by the split types are generated
+    let generated = generate_bindgen_token_stream(&btf).unwrap().to_string();
+    assert!(generated.contains("pub type M ="));
+    assert!(generated.contains("pub type mode_t ="));
+    assert!(generated.contains("pub type U ="));
+    assert!(!generated.contains("pub type T ="));
+    // Base types could still be selected explicitly
+    let generated = Builder::new()
+        .allowlist_type("T")
+        .generate(&btf)
+        .unwrap()
+        .to_string();
+    assert!(generated.contains("pub type T ="));
+    assert!(!generated.contains("pub type M ="));
+
+    let mut data = 0x1122334455667788u64.to_ne_bytes().to_vec();
+    data.extend((-5i32).to_ne_bytes());
+    data.extend(2i32.to_ne_bytes());
+    let Value::Struct(fields) = decode(&btf, "M", &data).unwrap() else {
+        panic!("M should be a struct");
+    };
+    assert_eq!(fields[1], ("cnt".to_string(), Value::Int(-5)));
+}
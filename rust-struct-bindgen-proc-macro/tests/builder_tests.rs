@@ -0,0 +1,43 @@
+use rust_struct_bindgen_impl::{btf::types::Btf, object::ElfFile, Builder};
+
+mod util;
+
+#[test]
+fn test_write_to_file() {
+    let elf_path = util::get_assets_dir().join("union_prog.bpf.o");
+    let out_path = std::env::temp_dir().join(format!(
+        "rust-struct-bindgen-builder-test-{}.rs",
+        std::process::id()
+    ));
+    Builder::from_elf_path(&elf_path)
+        .allowlist_type("T")
+        .emit_rerun_if_changed(false)
+        .write_to_file(&out_path)
+        .unwrap();
+    let written = std::fs::read_to_string(&out_path).unwrap();
+    std::fs::remove_file(&out_path).unwrap();
+
+    let elf_data = std::fs::read(&elf_path).unwrap();
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let expected = Builder::new()
+        .allowlist_type("T")
+        .generate(&btf)
+        .unwrap()
+        .to_string();
+    assert_eq!(written, expected);
+
+    let formatted = Builder::from_elf_path(&elf_path)
+        .rustfmt(true)
+        .generate_source()
+        .unwrap();
+    assert!(formatted.contains("\npub type T = inner_impl::"));
+    syn::parse_file(&formatted).unwrap();
+
+    assert!(Builder::new().generate_source().is_err());
+    assert!(
+        Builder::from_btf_path(util::get_assets_dir().join("no_such_file.btf"))
+            .generate_source()
+            .is_err()
+    );
+}
@@ -0,0 +1,10 @@
+#[test]
+fn test_compile_errors() {
+    // The test crates are built out of this crate, so the asset is given by an absolute path
+    std::env::set_var(
+        "TRUNCATED_BTF",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/assets/truncated.btf"),
+    );
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
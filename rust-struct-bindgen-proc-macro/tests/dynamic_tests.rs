@@ -0,0 +1,283 @@
+use rust_struct_bindgen_impl::{
+    btf::types::Btf,
+    dynamic::{decode, decode_with_options, encode, encode_with_options, Value},
+    object::ElfFile,
+    BindgenOptions,
+};
+
+mod util;
+
+fn field<'a>(value: &'a Value, name: &str) -> &'a Value {
+    match value {
+        Value::Struct(fields) | Value::Union(fields) => {
+            &fields.iter().find(|(n, _)| n == name).unwrap().1
+        }
+        s => panic!("Not a struct or union: {:?}", s),
+    }
+}
+
+#[test]
+fn test_dynamic_decoding() {
+    let elf_data = util::read_asset("simple_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let bin_data = util::read_asset("dumper_test.bin");
+    let st = decode(&btf, "S", &bin_data).unwrap();
+    assert_eq!(field(&st, "str"), &Value::String("A-String".into()));
+    let Value::Array(arr1) = field(&st, "arr1") else {
+        panic!("arr1 should be an array");
+    };
+    let Value::Array(arr1_1) = &arr1[1] else {
+        panic!("arr1[1] should be an array");
+    };
+    assert_eq!(
+        arr1_1[2],
+        Value::Array(
+            (0..4)
+                .map(|k| Value::Int((1 << 16) + (2 << 8) + k))
+                .collect()
+        )
+    );
+    let Value::Array(str_arr) = field(&st, "str_arr") else {
+        panic!("str_arr should be an array");
+    };
+    assert_eq!(str_arr[9], Value::String("hello 9".into()));
+    assert_eq!(field(&st, "ft"), &Value::Float(1.23f32 as f64));
+    assert_eq!(field(&st, "dbl"), &Value::Float(4.56));
+    assert_eq!(field(&st, "u64v"), &Value::Int(0x123456789abcdef0));
+    assert_eq!(field(&st, "i16v"), &Value::Int(-0x1234));
+    assert_eq!(
+        field(&st, "e"),
+        &Value::Enum {
+            name: "E_C".into(),
+            value: 2
+        }
+    );
+    assert!(decode(&btf, "S", &bin_data[1..]).is_err());
+    assert!(decode(&btf, "NotExist", &bin_data).is_err());
+}
+
+#[test]
+fn test_dynamic_decoding_bitfields_and_unions() {
+    let elf_data = util::read_asset("bitfield_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let st = decode(&btf, "BH", &bin_data).unwrap();
+    let bf = field(&st, "bf");
+    assert_eq!(field(bf, "b"), &Value::Int(-7));
+    assert_eq!(field(bf, "flag"), &Value::Bool(true));
+    assert_eq!(field(bf, "big"), &Value::Int(0x123456789a));
+    assert_eq!(
+        field(bf, "m"),
+        &Value::Enum {
+            name: "M_C".into(),
+            value: 2
+        }
+    );
+    assert_eq!(field(bf, "s"), &Value::Int(-200));
+    let bu = field(&st, "bu");
+    assert_eq!(field(bu, "whole"), &Value::Int(0x1234567d));
+    assert_eq!(field(bu, "low"), &Value::Int(0xd));
+}
+
+#[test]
+fn test_dynamic_encoding() {
+    for (elf_name, bin_name, ty) in [
+        ("simple_prog.bpf.o", "dumper_test.bin", "S"),
+        ("bitfield_prog.bpf.o", "bitfield_dump.bin", "BH"),
+        ("union_prog.bpf.o", "union_dump.bin", "T"),
+    ] {
+        let elf_data = util::read_asset(elf_name);
+        let elf = ElfFile::parse(&elf_data).unwrap();
+        let btf = Btf::load(&elf).unwrap();
+        let bin_data = util::read_asset(bin_name);
+        let value = decode(&btf, ty, &bin_data).unwrap();
+        assert_eq!(encode(&btf, ty, &value).unwrap(), bin_data);
+    }
+}
+
+#[test]
+fn test_dynamic_encoding_partial_and_errors() {
+    let elf_data = util::read_asset("simple_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let bytes = encode(
+        &btf,
+        "S",
+        &Value::Struct(vec![
+            ("u16v".into(), Value::Int(0x1234)),
+            ("e".into(), Value::String("E_B".into())),
+        ]),
+    )
+    .unwrap();
+    let st = decode(&btf, "S", &bytes).unwrap();
+    assert_eq!(field(&st, "u16v"), &Value::Int(0x1234));
+    assert_eq!(field(&st, "u32v"), &Value::Int(0));
+    assert_eq!(field(&st, "str"), &Value::String("".into()));
+    assert_eq!(
+        field(&st, "e"),
+        &Value::Enum {
+            name: "E_B".into(),
+            value: 1
+        }
+    );
+
+    let err = encode(
+        &btf,
+        "S",
+        &Value::Struct(vec![(
+            "str_arr".into(),
+            Value::Array(vec![
+                Value::String("short".into()),
+                Value::String("a string longer than twenty bytes".into()),
+            ]),
+        )]),
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("S.str_arr[1]: String is too long"));
+    let err = encode(
+        &btf,
+        "S",
+        &Value::Struct(vec![("e".into(), Value::String("E_X".into()))]),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "S.e: Invalid enum value E_X for enum E");
+    let err = encode(&btf, "S", &Value::Struct(vec![("e".into(), Value::Int(7))])).unwrap_err();
+    assert_eq!(err.to_string(), "S.e: Invalid enum value 7 for enum E");
+    let err = encode(
+        &btf,
+        "S",
+        &Value::Struct(vec![("i8v".into(), Value::Int(128))]),
+    )
+    .unwrap_err();
+    assert!(err.to_string().starts_with("S.i8v: Value 128 doesn't fit"));
+}
+
+#[test]
+fn test_dynamic_invalid_union_members() {
+    let elf_data = util::read_asset("string_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    // The text has no NUL byte, so only `num` can be decoded
+    let u = decode(&btf, "USTR", b"12345678").unwrap();
+    assert!(matches!(field(&u, "text"), Value::Invalid(e) if e.contains("zero byte not found")));
+    assert_eq!(
+        field(&u, "num"),
+        &Value::Int(u64::from_ne_bytes(*b"12345678") as i128)
+    );
+    // Invalid members are skipped when encoding
+    assert_eq!(encode(&btf, "USTR", &u).unwrap(), b"12345678");
+}
+
+#[test]
+fn test_dynamic_union_encoding() {
+    let elf_data = util::read_asset("union_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    let member = |name: &str, v: Value| (name.to_string(), v);
+    // Only the given member is written
+    let u = Value::Union(vec![member("i", Value::Int(-1))]);
+    assert_eq!(
+        encode(&btf, "U", &u).unwrap(),
+        [255, 255, 255, 255, 0, 0, 0, 0]
+    );
+    let u = Value::Struct(vec![member("ull", Value::Int(0x1122334455667788))]);
+    let expected = 0x1122334455667788u64.to_ne_bytes();
+    assert_eq!(encode(&btf, "U", &u).unwrap(), expected);
+    // Members describing the same bytes are accepted, whatever the order
+    let low = if cfg!(target_endian = "little") {
+        0x55667788
+    } else {
+        0x11223344
+    };
+    for fields in [
+        vec![
+            member("i", Value::Int(low)),
+            member("ull", Value::Int(0x1122334455667788)),
+        ],
+        vec![
+            member("ull", Value::Int(0x1122334455667788)),
+            member("i", Value::Int(low)),
+        ],
+    ] {
+        assert_eq!(encode(&btf, "U", &Value::Union(fields)).unwrap(), expected);
+    }
+    // Otherwise the result would depend on the order of the members
+    let u = Value::Union(vec![
+        member("i", Value::Int(1)),
+        member("ull", Value::Int(2)),
+    ]);
+    let err = encode(&btf, "U", &u).unwrap_err().to_string();
+    assert!(
+        err.contains("U: Members `i`, `ull` of the union describe different bytes"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_dynamic_malformed_ints() {
+    let mut btf_data = util::read_asset("unsupported_int.btf");
+    // The encoding word of the 3 bytes int `__u24`, holding its bit offset and bits
+    for (encoding, expected) in [
+        (
+            (8 << 16) | 24,
+            "Integer of 24 bits at bit offset 8 is out of its 3 bytes",
+        ),
+        (0, "Unsupported integer of 0 bits"),
+    ] {
+        btf_data[36..40].copy_from_slice(&(encoding as u32).to_le_bytes());
+        let btf = Btf::from_bytes(&btf_data).unwrap();
+        let err = format!("{:#}", decode(&btf, "E", &[1, 2, 3]).unwrap_err());
+        assert!(err.contains(expected), "{}", err);
+    }
+}
+
+#[test]
+fn test_dynamic_string_policies() {
+    let elf_data = util::read_asset("string_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let mut options = BindgenOptions::default();
+    options.set("string_policy", "lossy").unwrap();
+    options.set("field_string_policy", "STR.name=raw").unwrap();
+    let mut bin_data = vec![0u8; 24];
+    bin_data[..16].copy_from_slice(b"0123456789abcde\xff");
+    bin_data[16..21].copy_from_slice(b"init\0");
+    // The default strict policy rejects the invalid UTF-8
+    assert!(decode(&btf, "STR", &bin_data).is_err());
+    let st = decode_with_options(&btf, "STR", &bin_data, &options).unwrap();
+    assert_eq!(
+        field(&st, "comm"),
+        &Value::String("0123456789abcde\u{fffd}".into())
+    );
+    assert_eq!(
+        field(&st, "name"),
+        &Value::Array(
+            b"init\0\0\0\0"
+                .iter()
+                .map(|v| Value::Int(*v as i128))
+                .collect()
+        )
+    );
+    let mut expected = bin_data.clone();
+    expected[15] = 0;
+    let st = decode_with_options(&btf, "STR", &expected, &options).unwrap();
+    assert_eq!(
+        encode_with_options(&btf, "STR", &st, &options).unwrap(),
+        expected
+    );
+    // Raw arrays use all the bytes, with no room kept for NUL
+    let err = encode_with_options(
+        &btf,
+        "STR",
+        &Value::Struct(vec![("name".into(), Value::String("123456789".into()))]),
+        &options,
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("STR.name: String is too long! only 8 bytes"));
+}
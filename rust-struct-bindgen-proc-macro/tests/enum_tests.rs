@@ -0,0 +1,357 @@
+use rust_struct_bindgen_impl::{
+    btf::types::Btf,
+    dynamic::{decode, decode_with_options, encode, encode_with_options, Value},
+    BindgenOptions,
+};
+
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+    btf_struct_bindgen_with_btf!("assets/enum_prog.btf");
+}
+
+mod open_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+    btf_struct_bindgen_with_btf!("assets/enum_prog.btf", open_enum = "U32|S64");
+}
+
+mod open_bitfield_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/bitfield_prog.bpf.o", open_enum = ".*");
+}
+
+mod unknown_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+    btf_struct_bindgen_with_btf!("assets/unknown_enum.btf", open_enum = ".*");
+}
+
+mod alias_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/alias_prog.bpf.o");
+}
+
+mod flags_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(
+        "assets/flags_prog.bpf.o",
+        bitflags_enum = "Opt",
+        detect_bitflags = "true"
+    );
+}
+
+mod plain_flags_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/flags_prog.bpf.o");
+}
+
+mod util;
+
+fn ev_bytes(s32: i32, u32: u32, u64: u64, s64: i64) -> Vec<u8> {
+    let mut result = s32.to_ne_bytes().to_vec();
+    result.extend(u32.to_ne_bytes());
+    result.extend(u64.to_ne_bytes());
+    result.extend(s64.to_ne_bytes());
+    result
+}
+
+#[test]
+fn test_enum_signedness() {
+    use bindgen::inner_impl::{btf_type_2, btf_type_3, btf_type_4, btf_type_5};
+    let bin_data = ev_bytes(-2, 0x80000000, u64::MAX, i64::MIN);
+    let ev = bindgen::EV::from_bytes(&bin_data).unwrap();
+    assert!(matches!(ev.f_s32, btf_type_2::S_NEG));
+    assert!(matches!(ev.f_u32, btf_type_3::U_BIG));
+    assert!(matches!(ev.f_u64, btf_type_4::U64_MAX));
+    assert!(matches!(ev.f_s64, btf_type_5::S64_MIN));
+    assert_eq!(ev.to_bytes().unwrap(), bin_data);
+    // The repr follows the size
+    assert_eq!(std::mem::size_of::<btf_type_3>(), 4);
+    assert_eq!(std::mem::size_of::<btf_type_4>(), 8);
+
+    let bin_data = ev_bytes(7, 1, 1, -1);
+    let ev = bindgen::EV::from_bytes(&bin_data).unwrap();
+    assert!(matches!(ev.f_s32, btf_type_2::S_POS));
+    assert!(matches!(ev.f_u64, btf_type_4::U64_ONE));
+    assert_eq!(ev.to_bytes().unwrap(), bin_data);
+    assert!(bindgen::EV::from_bytes(&ev_bytes(0, 1, 1, -1)).is_err());
+}
+
+#[test]
+fn test_dynamic_enum_signedness() {
+    let btf_data = util::read_asset("enum_prog.btf");
+    let btf = Btf::from_bytes(&btf_data).unwrap();
+    let bin_data = ev_bytes(-2, 0x80000000, u64::MAX, i64::MIN);
+    let value = decode(&btf, "EV", &bin_data).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("EV should be a struct");
+    };
+    let values = fields
+        .iter()
+        .map(|(_, v)| match v {
+            Value::Enum { value, .. } => *value,
+            s => panic!("Not an enum: {:?}", s),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(values, [-2, 0x80000000, u64::MAX as i128, i64::MIN as i128]);
+    assert_eq!(encode(&btf, "EV", &value).unwrap(), bin_data);
+}
+
+#[test]
+fn test_open_enum() {
+    use open_bindgen::inner_impl::{btf_type_2, btf_type_3, btf_type_5};
+    let bin_data = ev_bytes(7, 0x12345678, 1, 42);
+    let ev = open_bindgen::EV::from_bytes(&bin_data).unwrap();
+    assert!(matches!(ev.f_s32, btf_type_2::S_POS));
+    assert!(matches!(ev.f_u32, btf_type_3::Unknown(0x12345678)));
+    assert!(matches!(ev.f_s64, btf_type_5::Unknown(42)));
+    // Unknown values are kept byte-exactly
+    assert_eq!(ev.to_bytes().unwrap(), bin_data);
+    let ev = open_bindgen::EV::from_bytes(&ev_bytes(7, 1, 1, -1)).unwrap();
+    assert!(matches!(ev.f_u32, btf_type_3::U_ONE));
+    // Enums not matching the patterns are still closed
+    assert!(open_bindgen::EV::from_bytes(&ev_bytes(0, 1, 1, -1)).is_err());
+}
+
+#[test]
+fn test_open_enum_unknown_member() {
+    use unknown_bindgen::State;
+    // The member `Unknown` keeps its name, and unknown values go to `Unknown_`
+    assert_eq!(State::from_value(0), Some(State::Unknown));
+    assert_eq!(State::from_value(5), Some(State::Unknown_(5)));
+    assert_eq!(
+        State::from_bytes(&0u32.to_ne_bytes()).unwrap(),
+        State::Unknown
+    );
+    assert_eq!("Unknown".parse::<State>().unwrap(), State::Unknown);
+    assert_eq!(State::Unknown_(5).to_string(), "5");
+    assert_eq!(State::VARIANTS, [("Unknown", 0), ("RUNNING", 1)]);
+}
+
+#[test]
+fn test_open_enum_bitfield() {
+    use open_bitfield_bindgen::Mode;
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let mut st = open_bitfield_bindgen::BH::from_bytes(&bin_data).unwrap();
+    assert!(matches!(st.f_bf.f_m, Mode::M_C));
+    // `enum Mode` has no enumerator of 3, which still fits in the 2 bits
+    st.f_bf.f_m = Mode::Unknown(3);
+    let st = open_bitfield_bindgen::BH::from_bytes(&st.to_bytes().unwrap()).unwrap();
+    assert!(matches!(st.f_bf.f_m, Mode::Unknown(3)));
+    assert_eq!(st.f_bf.f_s, -200i16);
+}
+
+#[test]
+fn test_enum_aliases() {
+    use alias_bindgen::{Level, State, AL};
+    let mut bin_data = 1u32.to_ne_bytes().to_vec();
+    bin_data.extend(0u32.to_ne_bytes());
+    let al = AL::from_bytes(&bin_data).unwrap();
+    // Values with aliases decode to the first member
+    assert_eq!(al.f_lv, Level::L_MID);
+    assert_eq!(al.f_st, State::ST_OFF);
+    // Aliases are consts of the same variant
+    assert!(matches!(al.f_lv, Level::L_DEFAULT));
+    assert!(matches!(al.f_st, State::ST_DISABLED));
+    assert_eq!(Level::L_MAX, Level::L_HIGH);
+    assert_eq!(
+        AL::from_bytes(&bin_data).unwrap().to_bytes().unwrap(),
+        bin_data
+    );
+    let al = AL {
+        f_lv: Level::L_MAX,
+        f_st: State::ST_ON,
+    };
+    assert_eq!(al.to_bytes().unwrap()[..4], 2u32.to_ne_bytes());
+
+    let elf_data = util::read_asset("alias_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    let Value::Struct(fields) = decode(&btf, "AL", &bin_data).unwrap() else {
+        panic!("AL should be a struct");
+    };
+    assert!(matches!(&fields[0].1, Value::Enum { name, .. } if name == "L_MID"));
+    assert!(matches!(&fields[1].1, Value::Enum { name, .. } if name == "ST_OFF"));
+}
+
+#[test]
+fn test_bitflags() {
+    use flags_bindgen::{Opt, Perm, FL};
+    let mut bin_data = 0x15u32.to_ne_bytes().to_vec();
+    bin_data.extend(3u32.to_ne_bytes());
+    bin_data.extend([0; 4]);
+    let mut fl = FL::from_bytes(&bin_data).unwrap();
+    assert!(fl.f_perm.contains(Perm::P_READ | Perm::P_EXEC));
+    assert!(!fl.f_perm.contains(Perm::P_WRITE));
+    // Bits not named by any member are kept
+    assert_eq!(format!("{:?}", fl.f_perm), "Perm(P_READ | P_EXEC | 0x10)");
+    assert_eq!(fl.to_bytes().unwrap(), bin_data);
+    // Members covering several bits are only listed if their bits are not listed yet
+    assert_eq!(fl.f_opt, Opt::O_ALL);
+    assert_eq!(format!("{:?}", fl.f_opt), "Opt(O_A | O_B)");
+    assert_eq!(format!("{:?}", Opt::O_NONE), "Opt(0x0)");
+
+    fl.f_perm.remove(Perm::P_EXEC);
+    fl.f_perm.insert(Perm::P_WRITE);
+    fl.f_small = Perm::P_WRITE | Perm::P_EXEC;
+    let fl = FL::from_bytes(&fl.to_bytes().unwrap()).unwrap();
+    assert_eq!(fl.f_perm.bits(), 0x13);
+    assert_eq!(fl.f_small, Perm::from_bits_retain(6));
+
+    let elf_data = util::read_asset("flags_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    let mut options = BindgenOptions::default();
+    options.set("bitflags_enum", "Opt").unwrap();
+    options.set("detect_bitflags", "true").unwrap();
+    let value = decode_with_options(&btf, "FL", &bin_data, &options).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("FL should be a struct");
+    };
+    assert!(matches!(&fields[0].1, Value::Enum { name, .. } if name == "P_READ | P_EXEC | 0x10"));
+    assert_eq!(
+        encode_with_options(&btf, "FL", &value, &options).unwrap(),
+        bin_data
+    );
+    let perm = Value::String("P_READ | P_WRITE".into());
+    let perm = encode_with_options(&btf, "Perm", &perm, &options).unwrap();
+    assert_eq!(perm, 3u32.to_ne_bytes());
+}
+
+#[test]
+fn test_bitflags_detection_opt_in() {
+    use plain_flags_bindgen::{Perm, FL};
+    // Without `detect_bitflags`, enums looking like flags stay plain enums
+    // `small` is `P_READ`
+    let mut bin_data = 4u32.to_ne_bytes().to_vec();
+    bin_data.extend([0, 0, 0, 0, 1, 0, 0, 0]);
+    let fl = FL::from_bytes(&bin_data).unwrap();
+    assert_eq!(fl.f_perm, Perm::P_EXEC);
+    bin_data[..4].copy_from_slice(&5u32.to_ne_bytes());
+    assert!(FL::from_bytes(&bin_data).is_err());
+
+    let elf_data = util::read_asset("flags_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    assert!(decode(&btf, "FL", &bin_data).is_err());
+    assert!(encode(&btf, "Perm", &Value::String("P_READ | P_WRITE".into())).is_err());
+    let mut options = BindgenOptions::default();
+    options.set("detect_bitflags", "true").unwrap();
+    let value = decode_with_options(&btf, "FL", &bin_data, &options).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("FL should be a struct");
+    };
+    assert!(matches!(&fields[0].1, Value::Enum { name, .. } if name == "P_READ | P_EXEC"));
+    // Open enums are not detected
+    options.set("open_enum", "Perm").unwrap();
+    let value = decode_with_options(&btf, "FL", &bin_data, &options).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("FL should be a struct");
+    };
+    assert_eq!(fields[0].1, Value::Int(5));
+    assert!(options.set("detect_bitflags", "yes").is_err());
+}
+
+#[test]
+fn test_dynamic_enum_options() {
+    let elf_data = util::read_asset("flags_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&elf_data).unwrap();
+    let mut options = BindgenOptions::default();
+    options.set("bitflags_enum", "Opt").unwrap();
+    let mut bin_data = 1u32.to_ne_bytes().to_vec();
+    bin_data.extend(5u32.to_ne_bytes());
+    bin_data.extend([1, 0, 0, 0]);
+    // `enum Opt` is only flags when selected, like the generated `flags_bindgen::Opt`
+    assert!(decode(&btf, "FL", &bin_data).is_err());
+    let value = decode_with_options(&btf, "FL", &bin_data, &options).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("FL should be a struct");
+    };
+    assert!(matches!(&fields[1].1, Value::Enum { name, .. } if name == "O_A | 0x4"));
+    assert_eq!(
+        format!(
+            "{:?}",
+            flags_bindgen::FL::from_bytes(&bin_data).unwrap().f_opt
+        ),
+        "Opt(O_A | 0x4)"
+    );
+    // The names and the remaining bits are parsed back
+    let json_like = Value::Struct(vec![("opt".into(), Value::String("O_A | 0x4".into()))]);
+    let bytes = encode_with_options(&btf, "FL", &json_like, &options).unwrap();
+    assert_eq!(bytes[4..8], 5u32.to_ne_bytes());
+    assert_eq!(
+        encode_with_options(&btf, "FL", &value, &options).unwrap(),
+        bin_data
+    );
+
+    let btf_data = util::read_asset("enum_prog.btf");
+    let btf = Btf::from_bytes(&btf_data).unwrap();
+    let mut options = BindgenOptions::default();
+    options.set("open_enum", "U32|S64").unwrap();
+    let bin_data = ev_bytes(7, 0x12345678, 1, 42);
+    assert!(decode(&btf, "EV", &bin_data).is_err());
+    // Unknown values of open enums are integers
+    let value = decode_with_options(&btf, "EV", &bin_data, &options).unwrap();
+    let Value::Struct(fields) = &value else {
+        panic!("EV should be a struct");
+    };
+    assert_eq!(fields[1].1, Value::Int(0x12345678));
+    assert_eq!(fields[3].1, Value::Int(42));
+    assert_eq!(
+        encode_with_options(&btf, "EV", &value, &options).unwrap(),
+        bin_data
+    );
+}
+
+#[test]
+fn test_enum_strings() {
+    use alias_bindgen::Level;
+    use bindgen::inner_impl::btf_type_2 as S32;
+    use bindgen::inner_impl::btf_type_4 as U64;
+    use open_bindgen::inner_impl::btf_type_3 as OpenU32;
+    assert_eq!(Level::L_HIGH.to_string(), "L_HIGH");
+    // Aliases print the name of the canonical member, and parse to it
+    assert_eq!(Level::L_DEFAULT.to_string(), "L_MID");
+    assert_eq!("L_DEFAULT".parse::<Level>().unwrap(), Level::L_MID);
+    assert_eq!("2".parse::<Level>().unwrap(), Level::L_HIGH);
+    assert!("L_NONE".parse::<Level>().is_err());
+    assert!("7".parse::<Level>().is_err());
+    assert_eq!(
+        Level::VARIANTS,
+        [
+            ("L_LOW", 0),
+            ("L_MID", 1),
+            ("L_HIGH", 2),
+            ("L_DEFAULT", 1),
+            ("L_MAX", 2)
+        ]
+    );
+
+    assert_eq!(S32::S_NEG.value(), -2i32);
+    assert_eq!(S32::from_value(7), Some(S32::S_POS));
+    assert_eq!(S32::from_value(0), None);
+    assert_eq!(S32::VARIANTS, [("S_NEG", -2), ("S_POS", 7)]);
+    // `u64` values above `i64::MAX` wrap in `VARIANTS`, but not in `value`
+    assert_eq!(U64::VARIANTS, [("U64_MAX", -1), ("U64_ONE", 1)]);
+    assert_eq!(U64::U64_MAX.value(), u64::MAX);
+
+    // Unknown values of open enums print as numbers
+    let unknown = OpenU32::from_value(5).unwrap();
+    assert_eq!(unknown, OpenU32::Unknown(5));
+    assert_eq!(unknown.to_string(), "5");
+    assert_eq!("5".parse::<OpenU32>().unwrap(), unknown);
+    assert_eq!("1".parse::<OpenU32>().unwrap(), OpenU32::U_ONE);
+    assert_eq!(OpenU32::U_BIG.value(), 0x80000000u32);
+
+    // Bit flags print the set flags and the remaining bits, and parse them back
+    use flags_bindgen::{Opt, Perm};
+    let perm = Perm::P_READ | Perm::P_EXEC | Perm::from_bits_retain(0x10);
+    assert_eq!(perm.to_string(), "P_READ | P_EXEC | 0x10");
+    assert_eq!("P_READ | P_EXEC | 0x10".parse::<Perm>().unwrap(), perm);
+    assert_eq!("P_WRITE|1".parse::<Perm>().unwrap().value(), 3);
+    assert!("P_READ | P_NONE".parse::<Perm>().is_err());
+    assert_eq!(Perm::empty().to_string(), "0x0");
+    assert_eq!("0x0".parse::<Perm>().unwrap(), Perm::empty());
+    // Aliases covering several bits parse to all of them
+    assert_eq!("O_ALL".parse::<Opt>().unwrap(), Opt::O_A | Opt::O_B);
+    assert_eq!(
+        Perm::VARIANTS,
+        [("P_READ", 1), ("P_WRITE", 2), ("P_EXEC", 4)]
+    );
+    assert_eq!(Perm::from_value(0x13), Some(Perm::from_bits_retain(0x13)));
+}
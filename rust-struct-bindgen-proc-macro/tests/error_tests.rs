@@ -0,0 +1,17 @@
+use rust_struct_bindgen_impl::{btf::types::Btf, Builder};
+
+mod util;
+
+#[test]
+fn test_error_context() {
+    let btf_data = util::read_asset("unsupported_int.btf");
+    let btf = Btf::from_bytes(&btf_data).unwrap();
+    let err = format!("{:#}", Builder::new().generate(&btf).unwrap_err());
+    assert!(err.contains("int `__u24` (type id 1)"), "{}", err);
+    assert!(
+        err.contains("used by field `weird` of struct `E`"),
+        "{}",
+        err
+    );
+    assert!(err.contains("Unsupported integer bits 24"), "{}", err);
+}
@@ -0,0 +1,64 @@
+use rust_struct_bindgen_impl::{btf::types::Btf, object::ElfFile, BindgenOptions, Builder};
+
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(
+        "assets/typedef_prog.bpf.o",
+        allowlist_type = "named",
+        blocklist_type = "pid_t|__u32"
+    );
+}
+
+mod util;
+
+#[test]
+fn test_filtered_bindings() {
+    let bin_data = util::read_asset("typedef_dump.bin");
+    let st = bindgen::named::from_bytes(&bin_data).unwrap();
+    // Referred types are still generated, and keep their aliases unless blocklisted
+    let tid: bindgen::tid_t = st.f_carr[1];
+    assert_eq!(tid, -20);
+    assert_eq!(st.f_in.f_b, -3);
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+}
+
+#[test]
+fn test_type_closure() {
+    let elf_data = util::read_asset("union_prog.bpf.o");
+    let elf = ElfFile::parse(&elf_data).unwrap();
+    let btf = Btf::load(&elf).unwrap();
+    let generate = |builder: Builder| builder.generate(&btf).unwrap().to_string();
+
+    let only_u = generate(Builder::new().allowlist_type("U"));
+    assert!(only_u.contains("pub type U ="));
+    assert!(!only_u.contains("pub type T ="));
+    // `float` is only referred by `U`, and `unsigned int` only by `T`
+    assert!(only_u.contains("f32"));
+    assert!(!only_u.contains("u32"));
+
+    let without_u = generate(Builder::new().blocklist_type("U"));
+    assert!(without_u.contains("pub type T ="));
+    assert!(!without_u.contains("pub type U ="));
+    assert!(without_u.contains("get_f"));
+
+    // Patterns match the whole name
+    let nothing = generate(Builder::new().allowlist_type("T."));
+    assert!(!nothing.contains("pub type T ="));
+    assert!(Builder::new().allowlist_type("(").generate(&btf).is_err());
+}
+
+#[test]
+fn test_options_by_name() {
+    let mut options = BindgenOptions::default();
+    options.set("allowlist_type", "U").unwrap();
+    options.set("blocklist_type", "T").unwrap();
+    assert_eq!(options.allowlist_type, ["U"]);
+    assert_eq!(options.blocklist_type, ["T"]);
+    options.set("open_enum", ".*").unwrap();
+    assert_eq!(options.open_enum, [".*"]);
+    options.set("bitflags_enum", "F").unwrap();
+    assert_eq!(options.bitflags_enum, ["F"]);
+    assert!(options.set("no_such_option", "x").is_err());
+    let builder = Builder::new().option("allowlist_type", "U").unwrap();
+    assert_eq!(builder.options().allowlist_type, ["U"]);
+}
@@ -0,0 +1,43 @@
+mod util;
+
+mod concat_path {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/assets/union_prog.bpf.o"
+    ));
+}
+
+mod concat_relative_path {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(concat!("assets/", "union_prog.bpf.o"), allowlist_type = "U");
+}
+
+mod out_dir_path {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    // Copied into `OUT_DIR` by the build script
+    btf_struct_bindgen_with_elf!(out_dir = "union_prog_out_dir.bpf.o", allowlist_type = "U");
+}
+
+mod env_path {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    // Set to the relative path of the asset by the build script
+    btf_struct_bindgen_with_elf!(env = "UNION_PROG_BPF_O", allowlist_type = "U");
+}
+
+#[test]
+fn test_path_expressions() {
+    let bin_data = util::read_asset("union_dump.bin");
+    let st = concat_path::T::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    let u = concat_relative_path::U::from_i(&-2).unwrap();
+    assert_eq!(u.get_i().unwrap(), -2);
+}
+
+#[test]
+fn test_out_dir_and_env_paths() {
+    let u = out_dir_path::U::from_i(&-2).unwrap();
+    assert_eq!(u.get_i().unwrap(), -2);
+    let u = env_path::U::from_i(&7).unwrap();
+    assert_eq!(u.get_i().unwrap(), 7);
+}
@@ -0,0 +1,236 @@
+use rust_struct_bindgen_impl::{
+    btf::types::Btf,
+    dynamic::{decode, Value},
+    Builder, StringPolicy,
+};
+
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/string_prog.bpf.o");
+}
+
+mod lossy_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/string_prog.bpf.o", string_policy = "lossy");
+}
+
+mod field_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(
+        "assets/string_prog.bpf.o",
+        string_policy = "nul_optional",
+        field_string_policy = "STR.comm=bytes",
+        field_string_policy = "STR.name=raw",
+        field_string_policy = "USTR.text=cstring"
+    );
+}
+
+mod bytes_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/bytes_prog.bpf.o");
+}
+
+mod bytes_text_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(
+        "assets/bytes_prog.bpf.o",
+        type_string_policy = "__u8=nul_optional",
+        field_string_policy = "PKT.key=raw",
+        field_string_policy = "PKT.name=raw"
+    );
+}
+
+mod util;
+
+/// `comm` filled up without a NUL byte, and `name` holding invalid UTF-8
+fn str_bytes(comm: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; 24];
+    result[..comm.len()].copy_from_slice(comm);
+    result[16..16 + name.len()].copy_from_slice(name);
+    result
+}
+
+#[test]
+fn test_strict_strings() {
+    let bin_data = str_bytes(b"systemd\0", b"init\0");
+    let st = bindgen::STR::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_comm, "systemd");
+    assert_eq!(st.f_name, "init");
+    assert_eq!(st.f_tail, "");
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    let err = bindgen::STR::from_bytes(&str_bytes(b"0123456789abcdef", b"\0"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("zero byte not found"), "{}", err);
+    assert!(bindgen::STR::from_bytes(&str_bytes(b"\xff\0", b"\0")).is_err());
+    // There must be room for the NUL byte
+    let mut st = st;
+    st.f_name = "12345678".into();
+    assert!(st.to_bytes().is_err());
+    st.f_name = "1234567".into();
+    assert!(st.to_bytes().is_ok());
+}
+
+#[test]
+fn test_lossy_strings() {
+    let bin_data = str_bytes(b"0123456789abcdef", b"a\xffb\0");
+    let st = lossy_bindgen::STR::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_comm, "0123456789abcdef");
+    assert_eq!(st.f_name, "a\u{fffd}b");
+    // A string filling the whole array is encoded without a NUL byte
+    assert_eq!(st.to_bytes().unwrap()[..16], bin_data[..16]);
+}
+
+#[test]
+fn test_field_string_policies() {
+    let bin_data = str_bytes(b"0123456789abcdef", b"a\xff\0b");
+    let mut st = field_bindgen::STR::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_comm, b"0123456789abcdef");
+    assert_eq!(st.f_name, *b"a\xff\0b\0\0\0\0");
+    // Fields not overridden follow the global policy
+    assert_eq!(st.f_tail, "");
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    st.f_comm = b"kworker".to_vec();
+    assert_eq!(st.to_bytes().unwrap()[..16], *b"kworker\0\0\0\0\0\0\0\0\0");
+    st.f_comm = vec![b'x'; 17];
+    assert!(st.to_bytes().is_err());
+
+    let un = field_bindgen::USTR::from_num(&0).unwrap();
+    assert_eq!(un.get_text().unwrap().as_bytes(), b"");
+    let text = std::ffi::CString::new("1234567").unwrap();
+    let un = field_bindgen::USTR::from_text(&text).unwrap();
+    assert_eq!(un.get_text().unwrap(), text);
+    let text = std::ffi::CString::new("12345678").unwrap();
+    assert!(field_bindgen::USTR::from_text(&text).is_err());
+}
+
+#[test]
+fn test_char_array_lengths() {
+    // `char comm[16]` under every policy
+    use field_bindgen::inner_impl::{
+        deserialize_btf_type_4, deserialize_btf_type_4_bytes, deserialize_btf_type_4_cstring,
+        deserialize_btf_type_4_raw,
+    };
+    for len in [0, 15, 17] {
+        let b = vec![b'a'; len];
+        for err in [
+            deserialize_btf_type_4(&b).unwrap_err(),
+            deserialize_btf_type_4_cstring(&b).unwrap_err(),
+            deserialize_btf_type_4_bytes(&b).unwrap_err(),
+            deserialize_btf_type_4_raw(&b).unwrap_err(),
+        ] {
+            assert_eq!(err, "Expected a slice with 16 bytes");
+        }
+    }
+    assert_eq!(deserialize_btf_type_4_raw(&[b'a'; 16]).unwrap(), [b'a'; 16]);
+}
+
+#[test]
+fn test_string_policy_options() {
+    let data = util::read_asset("string_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&data).unwrap();
+    let generated = Builder::new()
+        .field_string_policy("STR\\.name", StringPolicy::Raw)
+        .generate(&btf)
+        .unwrap()
+        .to_string();
+    assert!(
+        generated.contains("pub f_name : btf_type_5_raw"),
+        "{}",
+        generated
+    );
+    assert!(
+        generated.contains("pub f_comm : btf_type_4 ,"),
+        "{}",
+        generated
+    );
+
+    assert_eq!(
+        "nul_optional".parse::<StringPolicy>().unwrap(),
+        StringPolicy::NulOptional
+    );
+    assert_eq!(StringPolicy::CString.to_string(), "cstring");
+    for option in [
+        ("string_policy", "utf16"),
+        ("field_string_policy", "STR.name"),
+    ] {
+        let err = format!(
+            "{:#}",
+            Builder::new()
+                .option(option.0, option.1)
+                .and_then(|b| b.generate(&btf))
+                .unwrap_err()
+        );
+        assert!(err.contains("string policy"), "{}", err);
+    }
+}
+
+fn pkt_bytes() -> Vec<u8> {
+    let mut result = vec![0x00, 0x1b, 0x21, 0xff, 0xfe, 0x80];
+    result.extend([0xde, 0xad, 0xbe, 0xef]);
+    result.extend(b"eth0\0\0\0\0");
+    result.extend(b"lo\0\0");
+    result.extend(b"veth0\0");
+    result
+}
+
+#[test]
+fn test_byte_arrays() {
+    let bin_data = pkt_bytes();
+    let pkt = bytes_bindgen::PKT::from_bytes(&bin_data).unwrap();
+    // `unsigned char` and `__u8` arrays are bytes, `char` and `signed char` arrays are text
+    assert_eq!(pkt.f_mac, [0x00, 0x1b, 0x21, 0xff, 0xfe, 0x80]);
+    assert_eq!(pkt.f_key, [0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(pkt.f_label, *b"eth0\0\0\0\0");
+    assert_eq!(pkt.f_sname, "lo");
+    assert_eq!(pkt.f_name, "veth0");
+    assert_eq!(pkt.to_bytes().unwrap(), bin_data);
+
+    let data = util::read_asset("bytes_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&data).unwrap();
+    let Value::Struct(fields) = decode(&btf, "PKT", &bin_data).unwrap() else {
+        panic!("PKT should be a struct");
+    };
+    assert!(matches!(&fields[0].1, Value::Array(v) if v[5] == Value::Int(0x80)));
+    assert_eq!(fields[4].1, Value::String("veth0".into()));
+}
+
+#[test]
+fn test_byte_array_overrides() {
+    let bin_data = pkt_bytes();
+    let mut pkt = bytes_text_bindgen::PKT::from_bytes(&bin_data).unwrap();
+    // `__u8` arrays are text, except the overridden field
+    assert_eq!(pkt.f_label, "eth0");
+    assert_eq!(pkt.f_key, [0xde, 0xad, 0xbe, 0xef]);
+    // Arrays of `unsigned char` written as is don't match `__u8`
+    assert_eq!(pkt.f_mac, [0x00, 0x1b, 0x21, 0xff, 0xfe, 0x80]);
+    assert_eq!(pkt.f_sname, "lo");
+    assert_eq!(pkt.f_name, *b"veth0\0");
+    assert_eq!(pkt.to_bytes().unwrap(), bin_data);
+    pkt.f_label = "wlan0123".into();
+    assert_eq!(pkt.to_bytes().unwrap()[10..18], *b"wlan0123");
+
+    let data = util::read_asset("bytes_prog.bpf.o");
+    let btf = Btf::from_elf_bytes(&data).unwrap();
+    // Only `char` itself, not `signed char`
+    let generated = Builder::new()
+        .type_string_policy("char", StringPolicy::Raw)
+        .generate(&btf)
+        .unwrap()
+        .to_string();
+    assert!(
+        generated.contains("pub f_name : btf_type_11 }"),
+        "{}",
+        generated
+    );
+    assert!(
+        generated.contains("pub type btf_type_11 = [u8 ; 6usize]"),
+        "{}",
+        generated
+    );
+    assert!(
+        generated.contains("pub type btf_type_9 = String"),
+        "{}",
+        generated
+    );
+}
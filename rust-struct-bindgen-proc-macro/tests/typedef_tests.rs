@@ -0,0 +1,37 @@
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/typedef_prog.bpf.o");
+}
+
+mod util;
+
+#[test]
+fn test_typedef_deserializing() {
+    let bin_data = util::read_asset("typedef_dump.bin");
+    let st = bindgen::named::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_x, -1);
+    assert_eq!(st.f_y, 0x12345678);
+    assert_eq!(st.f_arr, [1, 2, 3]);
+    assert_eq!(st.f_carr, [-10, -20]);
+    assert_eq!(st.f_in.f_a, 0xdeadbeef);
+    assert_eq!(st.f_in.f_b, -3);
+    assert_eq!(st.f_ptr, 0);
+}
+
+#[test]
+fn test_typedef_serializing() {
+    let bin_data = util::read_asset("typedef_dump.bin");
+    let pid: bindgen::pid_t = -10;
+    let tid: bindgen::tid_t = -20;
+    let val: bindgen::__u32 = 0x12345678;
+    let st = bindgen::named {
+        f_x: -1,
+        f_y: val,
+        f_arr: [1, 2, 3],
+        f_carr: [pid, tid],
+        f_in: bindgen::anon_t::from_bytes(&bin_data[0x1c..0x24]).unwrap(),
+        f_ptr: 0,
+    };
+    assert_eq!(st.f_in.f_b, -3);
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+}
@@ -0,0 +1,6 @@
+use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+
+// Set to `assets/truncated.btf` of this crate by the test
+btf_struct_bindgen_with_btf!(env = "TRUNCATED_BTF");
+
+fn main() {}
@@ -0,0 +1,5 @@
+use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_btf;
+
+btf_struct_bindgen_with_btf!("assets/missing.btf");
+
+fn main() {}
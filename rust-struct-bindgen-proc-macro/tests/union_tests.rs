@@ -0,0 +1,30 @@
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/union_prog.bpf.o");
+}
+
+mod util;
+
+#[test]
+fn test_union_deserializing() {
+    let bin_data = util::read_asset("union_dump.bin");
+    let st = bindgen::T::from_bytes(&bin_data).unwrap();
+    assert_eq!(st.f_tag, 1);
+    assert_eq!(st.f_u.get_ull().unwrap(), 0x1122334455667788);
+    assert_eq!(st.f_u.get_i().unwrap(), 0x55667788);
+    assert_eq!(st.f_u.get_arr().unwrap(), [0x7788, 0x5566, 0x3344, 0x1122]);
+    assert_eq!(st.f_anon_2.get_raw().unwrap(), 0x12345678);
+    let half = st.f_anon_2.get_half().unwrap();
+    assert_eq!(half.f_lo, 0x5678);
+    assert_eq!(half.f_hi, 0x1234);
+}
+
+#[test]
+fn test_union_serializing() {
+    let bin_data = util::read_asset("union_dump.bin");
+    let mut st = bindgen::T::from_bytes(&bin_data).unwrap();
+    st.f_u = bindgen::U::from_ull(&0x1122334455667788).unwrap();
+    assert_eq!(st.to_bytes().unwrap(), bin_data);
+    st.f_u.set_i(&-1).unwrap();
+    assert_eq!(st.f_u.get_ull().unwrap(), 0x11223344ffffffff);
+}
@@ -3,3 +3,8 @@ use std::path::PathBuf;
 pub(crate) fn get_assets_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets")
 }
+/// Read a file in the assets directory. Panics if it fails
+#[allow(unused)]
+pub(crate) fn read_asset(name: &str) -> Vec<u8> {
+    std::fs::read(get_assets_dir().join(name)).unwrap()
+}
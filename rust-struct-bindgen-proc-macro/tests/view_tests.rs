@@ -0,0 +1,209 @@
+mod bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/simple_prog.bpf.o");
+}
+
+mod union_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/union_prog.bpf.o");
+}
+
+mod bitfield_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/bitfield_prog.bpf.o");
+}
+
+mod string_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!(
+        "assets/string_prog.bpf.o",
+        field_string_policy = "STR.name=raw"
+    );
+}
+
+mod nested_bindgen {
+    use rust_struct_bindgen_proc_macro::btf_struct_bindgen_with_elf;
+    btf_struct_bindgen_with_elf!("assets/nested_prog.bpf.o");
+}
+
+mod util;
+
+#[test]
+fn test_struct_view() {
+    let bin_data = util::read_asset("dumper_test.bin");
+    let view = bindgen::SView::new(&bin_data).unwrap();
+    assert_eq!(view.f_str().unwrap().to_str().unwrap(), "A-String");
+    assert_eq!(view.f_u32v(), 0x12345678);
+    assert_eq!(view.f_i64v(), -0x123456789abcdef0);
+    assert_eq!(view.f_dbl(), 4.56f64);
+    assert_eq!(view.f_e().unwrap(), bindgen::E::E_C);
+    // Arrays are read element by element
+    let arr1 = view.f_arr1();
+    assert_eq!(arr1.len(), 2);
+    assert_eq!(arr1.get(1).unwrap().get(2).unwrap().get(3), Some(0x10203));
+    assert!(arr1.get(2).is_none());
+    let str_arr = view.f_str_arr();
+    assert_eq!(str_arr.len(), 10);
+    for (i, s) in str_arr.iter().enumerate() {
+        assert_eq!(s.unwrap().to_str().unwrap(), format!("hello {}", i));
+    }
+    // Views agree with the owned bindings
+    let st = view.decode().unwrap();
+    assert_eq!(st.f_str, "A-String");
+    assert_eq!(view.bytes(), &bin_data[..]);
+
+    // The length is checked once when creating the view
+    assert!(bindgen::SView::new(&bin_data[..bin_data.len() - 1]).is_err());
+    let mut longer = bin_data.clone();
+    longer.extend([0; 8]);
+    let view = bindgen::SView::new(&longer).unwrap();
+    assert_eq!(view.bytes().len(), bin_data.len());
+}
+
+#[test]
+fn test_union_view() {
+    let bin_data = util::read_asset("union_dump.bin");
+    let view = union_bindgen::TView::new(&bin_data).unwrap();
+    assert_eq!(view.f_tag(), 1);
+    let u = view.f_u();
+    assert_eq!(u.get_ull(), 0x1122334455667788);
+    assert_eq!(u.get_i(), 0x55667788);
+    assert_eq!(
+        u.get_arr().iter().collect::<Vec<_>>(),
+        [0x7788, 0x5566, 0x3344, 0x1122]
+    );
+    // Anonymous members are views as well
+    assert_eq!(view.f_anon_2().get_raw(), 0x12345678);
+    let half = view.f_anon_2().get_half();
+    assert_eq!(half.f_lo(), 0x5678);
+    assert_eq!(half.f_hi(), 0x1234);
+    assert!(format!("{:?}", half).ends_with("{ lo: 22136, hi: 4660 }"));
+}
+
+#[test]
+fn test_bitfield_view() {
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let view = bitfield_bindgen::BHView::new(&bin_data).unwrap();
+    let bf = view.f_bf();
+    assert_eq!(bf.f_a(), 5u8);
+    assert_eq!(bf.f_b(), -7i8);
+    assert_eq!(bf.f_c(), 0xab);
+    assert!(bf.f_flag());
+    assert_eq!(bf.f_big(), 0x123456789au64);
+    assert_eq!(bf.f_m().unwrap(), bitfield_bindgen::Mode::M_C);
+    assert_eq!(bf.f_s(), -200i16);
+    assert_eq!(view.f_bu().get_low(), 0xd);
+}
+
+#[test]
+fn test_string_view() {
+    let mut bin_data = vec![0u8; 24];
+    bin_data[..16].copy_from_slice(b"0123456789abcdef");
+    bin_data[16..21].copy_from_slice(b"init\0");
+    let view = string_bindgen::STRView::new(&bin_data).unwrap();
+    // A strict string without NUL fails only when it's read
+    assert!(view.f_comm().is_err());
+    assert_eq!(view.f_name(), b"init\0\0\0\0");
+    assert_eq!(view.f_tail(), b"");
+
+    let view = string_bindgen::USTRView::new(b"text\0\0\0\0").unwrap();
+    assert_eq!(view.get_text().unwrap().to_bytes(), b"text");
+}
+
+#[test]
+fn test_struct_view_mut() {
+    let bin_data = util::read_asset("dumper_test.bin");
+    let mut buf = bin_data.clone();
+    let mut view = bindgen::SViewMut::new(&mut buf).unwrap();
+    view.set_u32v(&0xcafe).unwrap();
+    view.set_str(&"B-String".to_string()).unwrap();
+    view.set_e(&bindgen::E::E_A).unwrap();
+    // Array elements are written one by one
+    view.f_str_arr().set(3, &"patched".to_string()).unwrap();
+    let mut arr1 = view.f_arr1();
+    assert_eq!(arr1.len(), 2);
+    assert!(arr1.set(2, &[[0; 4]; 3]).is_err());
+    arr1.set(1, &[[7; 4]; 3]).unwrap();
+    // Failed writes leave the bytes unchanged
+    assert!(view
+        .set_str(&"a string longer than the array".to_string())
+        .is_err());
+    assert_eq!(view.view().f_u32v(), 0xcafe);
+
+    let st = bindgen::S::from_bytes(&buf).unwrap();
+    assert_eq!(st.f_u32v, 0xcafe);
+    assert_eq!(st.f_str, "B-String");
+    assert!(matches!(st.f_e, bindgen::E::E_A));
+    assert_eq!(st.f_str_arr[2], "hello 2");
+    assert_eq!(st.f_str_arr[3], "patched");
+    assert_eq!(st.f_arr1[0][1][2], 0x102);
+    assert_eq!(st.f_arr1[1], [[7; 4]; 3]);
+    assert_eq!(st.f_i64v, -0x123456789abcdef0);
+}
+
+#[test]
+fn test_view_mut_keeps_other_bytes() {
+    let bin_data = util::read_asset("union_dump.bin");
+    // Fill the bytes not covered by `int i` with a marker, including padding
+    let mut buf = vec![0xeeu8; bin_data.len()];
+    let mut view = union_bindgen::TViewMut::new(&mut buf).unwrap();
+    view.f_u().set_i(&-1).unwrap();
+    view.f_anon_2().get_half().set_hi(&0x1234).unwrap();
+    let mut expected = vec![0xeeu8; bin_data.len()];
+    expected[8..12].copy_from_slice(&(-1i32).to_ne_bytes());
+    expected[18..20].copy_from_slice(&0x1234u16.to_ne_bytes());
+    assert_eq!(buf, expected);
+
+    let bin_data = util::read_asset("bitfield_dump.bin");
+    let mut buf = bin_data.clone();
+    let mut view = bitfield_bindgen::BHViewMut::new(&mut buf).unwrap();
+    let mut bf = view.f_bf();
+    bf.set_b(&3).unwrap();
+    bf.set_m(&bitfield_bindgen::Mode::M_A).unwrap();
+    // Values not fitting in the bits are rejected
+    assert!(bf.set_a(&8).is_err());
+    let st = bitfield_bindgen::BH::from_bytes(&buf).unwrap();
+    assert_eq!(st.f_bf.f_a, 5);
+    assert_eq!(st.f_bf.f_b, 3);
+    assert!(matches!(st.f_bf.f_m, bitfield_bindgen::Mode::M_A));
+    assert_eq!(st.f_bf.f_s, -200);
+    assert_eq!(st.f_bf.f_big, 0x123456789a);
+}
+
+#[test]
+fn test_view_mut_whole_struct_keeps_padding() {
+    use nested_bindgen::{NSViewMut, NS, P};
+    let p = |x, c| P { f_x: x, f_c: c };
+    let ns = NS {
+        f_one: p(1, 2),
+        f_arr: [p(3, 4), p(5, 6)],
+        f_tail: 7,
+    };
+    // Fill the buffer with a marker, which must survive in all padding bytes
+    let mut buf = vec![0xeeu8; 28];
+    NSViewMut::new(&mut buf).unwrap().set(&ns).unwrap();
+    let mut expected = vec![0xeeu8; 28];
+    for (offset, x, c) in [(0, 1i32, 2u8), (8, 3, 4), (16, 5, 6)] {
+        expected[offset..offset + 4].copy_from_slice(&x.to_ne_bytes());
+        expected[offset + 4] = c;
+    }
+    expected[24..26].copy_from_slice(&7i16.to_ne_bytes());
+    assert_eq!(buf, expected);
+    let decoded = NS::from_bytes(&buf).unwrap();
+    assert_eq!(decoded.f_arr[1].f_x, 5);
+
+    // Members holding structs are written member by member as well
+    let mut view = NSViewMut::new(&mut buf).unwrap();
+    view.set_one(&p(8, 9)).unwrap();
+    view.set_arr(&[p(10, 11), p(12, 13)]).unwrap();
+    // Elements of arrays of structs are mutable views
+    let mut arr = view.f_arr();
+    arr.get_mut(1).unwrap().set_c(&14).unwrap();
+    assert!(arr.get_mut(2).is_none());
+    arr.set(0, &p(15, 16)).unwrap();
+    for (offset, x, c) in [(0, 8i32, 9u8), (8, 15, 16), (16, 12, 14)] {
+        expected[offset..offset + 4].copy_from_slice(&x.to_ne_bytes());
+        expected[offset + 4] = c;
+    }
+    assert_eq!(buf, expected);
+}